@@ -0,0 +1,6 @@
+// Propagate the chip / esp-idf version cfgs (`esp32`, `esp32c3`...) from esp-idf-sys
+fn main() -> anyhow::Result<()> {
+    embuild::build::CfgArgs::output_propagated("ESP_IDF")?;
+
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use crate::callback::EdgeCallback;
+use crate::clock::Clock;
+use crate::debounce::Debounce;
+use crate::deferred::DeferredTask;
+use crate::edge::{Level, MissedEdgePolicy};
+use crate::encoder::SampledPin;
+use crate::follower::Follower;
+use crate::gate::Gate;
+use crate::leveled_edge::LeveledEdge;
+use crate::pins::PullMode;
+use crate::service::IsrConfig;
+use crate::settling::SettlingMeter;
+use crate::sink::Sink;
+#[cfg(feature = "trace")]
+use crate::trace::DEFAULT_TRACE_DEPTH;
+use esp_idf_hal::gpio::{GpioPin, Input, Output};
+use esp_idf_sys::EspError;
+use std::time::Duration;
+
+/// Builder for `LeveledEdge` handles that need more than the defaults of `LeveledEdge::new`
+/// Created with `LeveledEdge::builder`
+pub struct LeveledEdgeBuilder<Debouncer, Func>
+where
+    Debouncer: Debounce,
+    Func: EdgeCallback,
+{
+    pub(crate) gpio: GpioPin<Input>,
+    pub(crate) debouncer: Debouncer,
+    pub(crate) callback: Func,
+    pub(crate) active: Level,
+    pub(crate) isr_config: IsrConfig,
+    pub(crate) output: Option<Follower>,
+    pub(crate) sinks: Vec<Sink>,
+    pub(crate) missed_edge_policy: MissedEdgePolicy,
+    pub(crate) deferred: Option<DeferredTask>,
+    pub(crate) sampled_pin: Option<SampledPin>,
+    pub(crate) user_data: u32,
+    pub(crate) label: Option<&'static str>,
+    pub(crate) disable_during_isr: bool,
+    pub(crate) arming_delay: Duration,
+    pub(crate) gate: Option<Box<dyn Gate>>,
+    pub(crate) pull: Option<PullMode>,
+    pub(crate) settling: Option<SettlingMeter>,
+    pub(crate) clock: Option<Box<dyn Clock>>,
+    pub(crate) initial_level: Option<bool>,
+    pub(crate) emit_initial_state: bool,
+    pub(crate) unstable: Option<(u32, Duration)>,
+    pub(crate) on_unstable: Option<Box<dyn FnMut()>>,
+    #[cfg(feature = "trace")]
+    pub(crate) trace_depth: usize,
+}
+
+impl<Debouncer, Func> LeveledEdgeBuilder<Debouncer, Func>
+where
+    Debouncer: Debounce,
+    Func: EdgeCallback,
+{
+    pub(crate) fn new(gpio: GpioPin<Input>, debouncer: Debouncer, callback: Func) -> Self {
+        Self {
+            gpio,
+            debouncer,
+            callback,
+            active: Level::High,
+            isr_config: IsrConfig::default(),
+            output: None,
+            sinks: Vec::new(),
+            missed_edge_policy: MissedEdgePolicy::default(),
+            deferred: None,
+            sampled_pin: None,
+            user_data: 0,
+            label: None,
+            disable_during_isr: true,
+            arming_delay: Duration::ZERO,
+            gate: None,
+            pull: None,
+            settling: None,
+            clock: None,
+            initial_level: None,
+            emit_initial_state: false,
+            unstable: None,
+            on_unstable: None,
+            #[cfg(feature = "trace")]
+            trace_depth: DEFAULT_TRACE_DEPTH,
+        }
+    }
+
+    /// The level at which the input is considered active (pressed), `Level::High` by default.
+    ///
+    /// With `Level::Low` (a button with a pull up) the callback gets `true` when the pin goes low,
+    /// so it can think in pressed / released instead of sprinkling `!state` everywhere.
+    /// `LeveledEdge::current_state` keeps reporting the raw electrical level.
+    pub fn active(mut self, active: Level) -> Self {
+        self.active = active;
+        self
+    }
+
+    /// How the global gpio isr service is handled, see `IsrConfig`
+    pub fn isr_config(mut self, isr_config: IsrConfig) -> Self {
+        self.isr_config = isr_config;
+        self
+    }
+
+    /// Also drive `output` to mirror the debounced input (see `LeveledEdge::follow`), on top of the callback.
+    /// On every accepted edge the output is set first and the callback called after it,
+    /// so the callback already sees the output at its new level.
+    /// invert: drive the output low while the input is high and the other way around
+    pub fn output(mut self, output: GpioPin<Output>, invert: bool) -> Self {
+        self.output = Some(Follower::new(output, invert));
+        self
+    }
+
+    /// Wait this long (in `build`, not in the isr) between configuring the interrupt and enabling it,
+    /// then read the pin again to arm the right level. For RC filtered inputs that are still settling
+    /// right after the pin was configured and would otherwise fire a phantom edge. 0 by default.
+    pub fn arming_delay(mut self, arming_delay: Duration) -> Self {
+        self.arming_delay = arming_delay;
+        self
+    }
+
+    /// Run every edge the debouncer accepted through `gate` first, only the ones it allows are delivered
+    /// (output, callback and sinks). See `Gate` for what it may do in the isr.
+    pub fn gate(mut self, gate: impl Gate + 'static) -> Self {
+        self.gate = Some(Box::new(gate));
+        self
+    }
+
+    /// How many of the last edges `LeveledEdge::trace` keeps, `DEFAULT_TRACE_DEPTH` by default.
+    /// The ring is allocated once in `build`, 0 turns tracing off for this handle.
+    #[cfg(feature = "trace")]
+    pub fn trace_depth(mut self, depth: usize) -> Self {
+        self.trace_depth = depth;
+        self
+    }
+
+    /// Measure how long the switch bounced after every accepted edge, from the edge to the last bounce
+    /// the debouncer rejected, to grade switches on a test jig. `callback` gets the settling time of an edge
+    /// from the interrupt handler when the next edge is accepted (a press is reported on its release).
+    /// Only bounces the debouncer rejects are seen, it needs one with a window (with `NoDebounce`
+    /// every bounce is an edge of its own), and bounces shorter than the handler's settling reads are not.
+    pub fn settling_meter(mut self, callback: impl FnMut(Duration) + 'static) -> Self {
+        self.settling = Some(SettlingMeter::new(callback));
+        self
+    }
+
+    /// Hand out timestamps in `clock` instead of `esp_timer` time, e.g. `SystemClock` to line edges up
+    /// with the absolute time of a data log. The edges are still timed with `esp_timer` in the isr,
+    /// they are only translated when read back, see `Clock`.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Refuse to arm unless the pin reads `level` (`true` for high, like `LeveledEdge::current_state`),
+    /// for interlocks that must not start out in an unsafe state (e.g. a guard that is open).
+    /// `build` / `LeveledEdge::arm` fail with `ESP_ERR_INVALID_STATE` otherwise, `arm` can be tried again later.
+    /// The pin is read once, when arming. Once armed it is free to go to any level, nothing enforces it.
+    pub fn require_initial_level(mut self, level: bool) -> Self {
+        self.initial_level = Some(level);
+        self
+    }
+
+    /// Call the callback once with the level the pin is at when the handle is armed, so the app
+    /// doesn't have to read the starting state on its own. It is called from the task that arms the handle
+    /// (in `build` or `LeveledEdge::arm`), with `EdgeContext::InitialState` for `WithContext` callbacks.
+    pub fn emit_initial_state(mut self) -> Self {
+        self.emit_initial_state = true;
+        self
+    }
+
+    /// Flag the input as unstable once more than `threshold` edges in a row were rejected by the debouncer,
+    /// the run starting over if it takes longer than `window`. A pin stuck oscillating (bad wiring, a failing
+    /// switch) otherwise goes unnoticed, the debouncer silently rejects everything it does.
+    /// See `LeveledEdge::is_input_unstable`, the flag is cleared by the next accepted edge.
+    pub fn detect_unstable(mut self, threshold: u32, window: Duration) -> Self {
+        self.unstable = Some((threshold, window));
+        self
+    }
+
+    /// Also call `callback` when the input becomes unstable, see `detect_unstable` (needed for it to fire).
+    /// Called once per unstable spell, from the interrupt handler.
+    pub fn on_unstable(mut self, callback: impl FnMut() + 'static) -> Self {
+        self.on_unstable = Some(Box::new(callback));
+        self
+    }
+
+    /// An id of the application's choosing, passed to `Tagged` callbacks and kept on the handle,
+    /// independent of the gpio number. 0 by default.
+    pub fn user_data(mut self, user_data: u32) -> Self {
+        self.user_data = user_data;
+        self
+    }
+
+    /// A name for the input in diagnostics (`LeveledEdge::telemetry`, `trace`, `crash_summary`),
+    /// e.g. `"volume_up"`, easier to read than a gpio number in a dump of many inputs. None by default.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Whether the missed edges the handle finds are delivered or only fixed, see `MissedEdgePolicy`.
+    /// `MissedEdgePolicy::FireCallback` by default. The corrections of `LeveledEdge::verify_and_correct`
+    /// are delivered from the task that runs the check, the others from the interrupt handler.
+    pub fn missed_edge_policy(mut self, policy: MissedEdgePolicy) -> Self {
+        self.missed_edge_policy = policy;
+        self
+    }
+
+    /// Same as `missed_edge_policy`, `true` for `FireCallback` and `false` for `SilentResync`
+    pub fn notify_corrections(self, notify: bool) -> Self {
+        self.missed_edge_policy(match notify {
+            true => MissedEdgePolicy::FireCallback,
+            false => MissedEdgePolicy::SilentResync,
+        })
+    }
+
+    /// Whether the interrupt handler turns the pin's interrupt off while it runs, `true` by default.
+    ///
+    /// The gpio isr service clears the status of a level interrupt only after the handler returned,
+    /// so if the trigger is still at the level the pin is at by then, the handler is called again right
+    /// away, and again, for as long as the level holds. A polling debouncer and backpressure
+    /// (`new_deferred_backpressure`) return without re-arming, they need the interrupt off until
+    /// they are done. The plain handler re-arms the opposite level before it returns, so it is safe without,
+    /// and `false` saves it the disable and enable (both take the gpio spinlock) on every edge.
+    /// `build` fails with `ESP_ERR_INVALID_ARG` on `false` with a polling debouncer or backpressure.
+    /// A `Controlled` callback must not return `EdgeAction::NextLevel` with the level the pin is at then.
+    pub fn disable_during_isr(mut self, disable: bool) -> Self {
+        self.disable_during_isr = disable;
+        self
+    }
+
+    /// Add another consumer of the edges, up to `Sink::MAX_SINKS`
+    /// On every accepted edge the sinks run after the callback, in the order they were added,
+    /// all of them in the interrupt handler (a `Sink::deferred` only queues the edge there). `build` fails with `ESP_ERR_INVALID_ARG` if there are too many.
+    pub fn sink(mut self, sink: Sink) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub(crate) fn deferred(mut self, task: DeferredTask) -> Self {
+        self.deferred = Some(task);
+        self
+    }
+
+    pub(crate) fn sampled_pin(mut self, sampled_pin: SampledPin) -> Self {
+        self.sampled_pin = Some(sampled_pin);
+        self
+    }
+
+    /// The pull the pin was set up with, so `LeveledEdge::reassert` can apply it again
+    pub(crate) fn pull(mut self, pull: PullMode) -> Self {
+        self.pull = Some(pull);
+        self
+    }
+
+    /// Configure the pin and install the interrupt
+    pub fn build(self) -> Result<Box<LeveledEdge<Debouncer, Func>>, EspError> {
+        let mut handle = LeveledEdge::create(self)?;
+        handle.arm()?;
+
+        Ok(handle)
+    }
+
+    /// Same as `build`, but leave the interrupt uninstalled until `LeveledEdge::arm`,
+    /// e.g. to create the handles during init and only start getting edges once the system is up
+    pub fn prepare(self) -> Result<Box<LeveledEdge<Debouncer, Func>>, EspError> {
+        LeveledEdge::create(self)
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The handler may run
+const IDLE: u8 = 0;
+/// The handler is running
+const RUNNING: u8 = 1;
+/// A task closed it, the handler is skipped until `reopen`
+const CLOSED: u8 = 2;
+
+/// Whether a handler that no lock can keep out (an isr, maybe on the other core, or a callback
+/// of the esp_timer task) is running, so a task can wait for it and keep it from starting again
+/// while it changes what the handler uses. The handler never waits, it skips its run when closed.
+pub(crate) struct Busy {
+    state: AtomicU8,
+}
+
+impl Busy {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(IDLE),
+        }
+    }
+
+    /// Called by the handler on its way in, `false` if it is closed and must not run
+    #[inline(always)]
+    pub(crate) fn enter(&self) -> bool {
+        self.state
+            .compare_exchange(IDLE, RUNNING, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Called by the handler on its way out, after `enter` let it in
+    #[inline(always)]
+    pub(crate) fn leave(&self) {
+        self.state.store(IDLE, Ordering::Release);
+    }
+
+    /// Keep the handler out, `false` if it is running right now: the caller waits
+    /// the way that suits the handler and tries again. Closing it twice is fine.
+    pub(crate) fn try_close(&self) -> bool {
+        self.state
+            .compare_exchange(IDLE, CLOSED, Ordering::AcqRel, Ordering::Acquire)
+            != Err(RUNNING)
+    }
+
+    /// Let the handler run again
+    pub(crate) fn reopen(&self) {
+        let _ = self
+            .state
+            .compare_exchange(CLOSED, IDLE, Ordering::AcqRel, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_while_idle_only() {
+        let busy = Busy::new();
+
+        assert!(busy.enter());
+        // the handler is running, wait for it
+        assert!(!busy.try_close());
+        busy.leave();
+
+        assert!(busy.try_close());
+        assert!(busy.try_close());
+        assert!(!busy.enter());
+
+        busy.reopen();
+        assert!(busy.enter());
+    }
+}
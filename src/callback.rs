@@ -0,0 +1,156 @@
+use crate::edge::EdgeFilter;
+use esp_idf_hal::gpio::InterruptType;
+use esp_idf_sys::c_types::c_void;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// What the interrupt handler should do after a callback, see `Controlled`
+pub enum EdgeAction {
+    /// Arm the opposite level as usual
+    Continue,
+    /// Arm this interrupt type for the next edge instead of the opposite level.
+    /// For advanced users only (bit banged protocols where the next transition is known),
+    /// arming anything but the opposite level of the pin leaves the tracked state out of sync
+    /// with the pin until the interrupt fires, which is exactly what this crate is there to prevent.
+    NextLevel(InterruptType),
+}
+
+/// Why a callback is called, see `WithContext`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeContext {
+    /// An edge the interrupt handler caught
+    Normal,
+    /// The level the pin is at when the handle is armed, see `LeveledEdgeBuilder::emit_initial_state`
+    InitialState,
+    /// The interrupt handler found the pin at the level it was already at, so the edge away from it
+    /// was missed and this one puts the handle back in sync (no real press may be behind it)
+    MissedEdgeRecovery,
+    /// A mismatch fixed by `LeveledEdge::verify_and_correct`, called from the task that ran the check
+    Forced,
+}
+
+/// Anything a handle can call on an accepted edge
+/// Implemented for every `FnMut(bool)` closure, wrap a closure in `Controlled` to return an `EdgeAction`
+/// or in `Tagged` to get the handle's user data.
+pub trait EdgeCallback {
+    /// user_data: The value set with `LeveledEdgeBuilder::user_data`, 0 by default
+    fn on_edge(&mut self, active: bool, user_data: u32) -> EdgeAction;
+
+    /// What the handle actually calls, with the reason it does. Forwards to `on_edge` by default,
+    /// only callbacks that care about the reason need to implement it.
+    #[inline(always)]
+    fn on_edge_with_context(
+        &mut self,
+        active: bool,
+        user_data: u32,
+        _context: EdgeContext,
+    ) -> EdgeAction {
+        self.on_edge(active, user_data)
+    }
+}
+
+impl<F> EdgeCallback for F
+where
+    F: FnMut(bool),
+{
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, _user_data: u32) -> EdgeAction {
+        self(active);
+        EdgeAction::Continue
+    }
+}
+
+/// A callback that tells the interrupt handler what to do next, e.g. `Controlled(|state| EdgeAction::Continue)`
+pub struct Controlled<F>(pub F)
+where
+    F: FnMut(bool) -> EdgeAction;
+
+impl<F> EdgeCallback for Controlled<F>
+where
+    F: FnMut(bool) -> EdgeAction,
+{
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, _user_data: u32) -> EdgeAction {
+        (self.0)(active)
+    }
+}
+
+/// A callback that also gets the user data of the handle, `Tagged(|id, state| ...)`
+/// Handy for sharing one callback between many handles, the id stays the same when pins move around.
+pub struct Tagged<F>(pub F)
+where
+    F: FnMut(u32, bool);
+
+impl<F> EdgeCallback for Tagged<F>
+where
+    F: FnMut(u32, bool),
+{
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, user_data: u32) -> EdgeAction {
+        (self.0)(user_data, active);
+        EdgeAction::Continue
+    }
+}
+
+/// A callback that also gets the reason it is called, `WithContext(|state, context| ...)`
+/// For apps that treat a recovered edge or the initial state differently from a real press.
+pub struct WithContext<F>(pub F)
+where
+    F: FnMut(bool, EdgeContext);
+
+impl<F> EdgeCallback for WithContext<F>
+where
+    F: FnMut(bool, EdgeContext),
+{
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, _user_data: u32) -> EdgeAction {
+        (self.0)(active, EdgeContext::Normal);
+        EdgeAction::Continue
+    }
+
+    #[inline(always)]
+    fn on_edge_with_context(
+        &mut self,
+        active: bool,
+        _user_data: u32,
+        context: EdgeContext,
+    ) -> EdgeAction {
+        (self.0)(active, context);
+        EdgeAction::Continue
+    }
+}
+
+/// A callback that only counts the edges that pass `edge` into `counter`, see `LeveledEdge::count_into`
+pub struct EdgeCounter {
+    pub counter: &'static AtomicU32,
+    /// Relative to the active level, like the callback's argument (`Rising` = to active)
+    pub edge: EdgeFilter,
+}
+
+impl EdgeCallback for EdgeCounter {
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, _user_data: u32) -> EdgeAction {
+        if self.edge.matches(active) {
+            self.counter.fetch_add(1, Ordering::Relaxed);
+        }
+        EdgeAction::Continue
+    }
+}
+
+/// A plain function pointer and the context it is called with, the callback of `RawLeveledEdge`
+///
+/// Every closure is a type of its own, and every handle type is compiled again for it.
+/// All the handles with a `RawCallback` (and the same debouncer type) share one copy of the code instead,
+/// at the cost of an indirect call per edge and an untyped context, worth it when there are many
+/// handles on a module short on flash.
+pub struct RawCallback {
+    pub func: extern "C" fn(bool, *mut c_void),
+    pub context: *mut c_void,
+}
+
+impl EdgeCallback for RawCallback {
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, _user_data: u32) -> EdgeAction {
+        (self.func)(active, self.context);
+        EdgeAction::Continue
+    }
+}
@@ -0,0 +1,25 @@
+use esp_idf_sys::gpio_num_t_GPIO_NUM_MAX;
+
+/// What the current chip (and build of this crate) can do, see `capabilities`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The number of gpios of the chip, valid pin numbers are below it
+    pub gpio_count: u32,
+    /// The gpios have a hardware glitch filter (esp32c3 and esp32s3)
+    pub glitch_filter: bool,
+    /// `PcntEncoder` is available, the chip has a PCNT and the `pcnt` feature is on
+    pub pcnt_encoder: bool,
+    /// The chip has RTC gpios, which can wake it from deep sleep on a level
+    pub rtc_wakeup_pins: bool,
+}
+
+/// Tell a portable app what it can use on the chip it runs on, instead of it guessing per chip.
+/// Decided when the crate is built for the target, it doesn't probe the hardware.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        gpio_count: gpio_num_t_GPIO_NUM_MAX as u32,
+        glitch_filter: cfg!(any(esp32c3, esp32s3)),
+        pcnt_encoder: cfg!(all(feature = "pcnt", not(esp32c3))),
+        rtc_wakeup_pins: cfg!(any(esp32, esp32s2, esp32s3)),
+    }
+}
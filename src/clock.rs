@@ -0,0 +1,41 @@
+use crate::debounce::micros;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The time base of the timestamps a handle hands out, see `LeveledEdgeBuilder::clock`
+///
+/// The interrupt handler keeps timing everything with `esp_timer`, which is fast and monotonic,
+/// and `translate` only runs when a timestamp is read back (`LeveledEdge::last_edge_timestamp`,
+/// `LeveledEdge::trace`), in the task that reads it, never in the isr. So it may be as slow as it needs,
+/// but it is paid on every timestamp read (once per entry for a trace).
+pub trait Clock {
+    /// Turn an `esp_timer_get_time` value into the same instant in the application's clock, in microseconds
+    fn translate(&self, esp_timer_us: i64) -> i64;
+}
+
+impl<F> Clock for F
+where
+    F: Fn(i64) -> i64,
+{
+    fn translate(&self, esp_timer_us: i64) -> i64 {
+        self(esp_timer_us)
+    }
+}
+
+/// Microseconds since the unix epoch, as the system clock has it (set by SNTP or from an RTC)
+///
+/// The offset to `esp_timer` is taken again on every translation, so a timestamp follows the system clock
+/// as it is when it's read: an edge read back after the clock was stepped lands on the new time base,
+/// and a clock that was never set gives times around 1970. Costs a `gettimeofday` and an `esp_timer` read.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn translate(&self, esp_timer_us: i64) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_micros() as i64)
+            .unwrap_or(0);
+
+        now - (micros() - esp_timer_us)
+    }
+}
@@ -0,0 +1,123 @@
+use crate::debounce::{ClassicDebounce, Debounce, FilterDebounce, NoDebounce};
+use crate::edge::{EdgeFilter, Level};
+use crate::pins::PullMode;
+use esp_idf_sys::EspError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// The debouncers a `LeveledEdgeConfig` can pick from, the ones set up by a single time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DebounceKind {
+    /// `NoDebounce`, the debounce time is ignored
+    None,
+    /// `ClassicDebounce`
+    Classic,
+    /// `FilterDebounce`
+    Filter,
+}
+
+/// A handle described as data, for firmware that keeps its inputs in NVS or a JSON file
+/// and creates them at boot, see `LeveledEdge::from_config`. With the `serde` feature
+/// it can be serialized and deserialized as is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeveledEdgeConfig {
+    /// The gpio number, it has to be able to raise interrupts (see `InterruptCapable`)
+    pub gpio: i32,
+    pub pull: PullMode,
+    pub debounce: DebounceKind,
+    /// Has to be more than 0 for anything but `DebounceKind::None`
+    pub debounce_ms: u32,
+    /// Which edges are delivered, see `LeveledEdge::set_edge_filter`
+    pub edge_filter: EdgeFilter,
+    /// See `LeveledEdgeBuilder::active`
+    pub active: Level,
+}
+
+/// Why `LeveledEdge::from_config` refused a config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The gpio can't be used for interrupts on this chip (out of range, or a flash pin)
+    InvalidPin(i32),
+    /// A debouncer other than `DebounceKind::None` with a debounce time of 0
+    ZeroDebounceTime,
+    /// The config was fine, setting up the pin or the interrupt failed
+    Esp(EspError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidPin(gpio) => {
+                write!(f, "gpio {} can't be used for interrupts", gpio)
+            }
+            ConfigError::ZeroDebounceTime => write!(f, "the debounce time can't be 0"),
+            ConfigError::Esp(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<EspError> for ConfigError {
+    fn from(err: EspError) -> Self {
+        ConfigError::Esp(err)
+    }
+}
+
+/// The debouncer of a handle made by `LeveledEdge::from_config`, whichever the config picked
+pub enum ConfigDebounce {
+    None(NoDebounce),
+    Classic(ClassicDebounce),
+    Filter(FilterDebounce),
+}
+
+impl ConfigDebounce {
+    pub(crate) fn new(kind: DebounceKind, debounce_time: Duration) -> Self {
+        match kind {
+            DebounceKind::None => ConfigDebounce::None(NoDebounce),
+            DebounceKind::Classic => ConfigDebounce::Classic(ClassicDebounce::new(debounce_time)),
+            DebounceKind::Filter => ConfigDebounce::Filter(FilterDebounce::new(debounce_time)),
+        }
+    }
+
+    #[inline(always)]
+    fn inner(&mut self) -> &mut dyn Debounce {
+        match self {
+            ConfigDebounce::None(debouncer) => debouncer,
+            ConfigDebounce::Classic(debouncer) => debouncer,
+            ConfigDebounce::Filter(debouncer) => debouncer,
+        }
+    }
+}
+
+impl Debounce for ConfigDebounce {
+    #[inline(always)]
+    fn is_isr_valid(&mut self) -> bool {
+        self.inner().is_isr_valid()
+    }
+
+    #[inline(always)]
+    fn is_edge_valid(&mut self, active: bool) -> bool {
+        self.inner().is_edge_valid(active)
+    }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.inner().boost(temporary, for_)
+    }
+
+    fn reset(&mut self) {
+        self.inner().reset()
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        match self {
+            ConfigDebounce::None(debouncer) => debouncer.strategy_name(),
+            ConfigDebounce::Classic(debouncer) => debouncer.strategy_name(),
+            ConfigDebounce::Filter(debouncer) => debouncer.strategy_name(),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use esp_idf_hal::interrupt::{CriticalSection, CriticalSectionGuard};
+use std::cell::UnsafeCell;
+
+/// The `critical-section` implementation of the `critical_section_impl` feature
+///
+/// A single FreeRTOS spinlock (esp-idf-hal's `CriticalSection`): it masks the interrupts of the core
+/// that takes it and keeps the other core out, so it is correct on single and dual core chips alike,
+/// and can be taken from a task or an isr. It nests on the core holding it, only the outermost
+/// release lets it go.
+struct EspIdfCriticalSection;
+
+static LOCK: CriticalSection = CriticalSection::new();
+
+/// The guard of the outermost acquire and the nesting depth, only touched by the core holding `LOCK`
+struct Held {
+    guard: UnsafeCell<Option<CriticalSectionGuard<'static>>>,
+    depth: UnsafeCell<u32>,
+}
+
+unsafe impl Sync for Held {}
+
+static HELD: Held = Held {
+    guard: UnsafeCell::new(None),
+    depth: UnsafeCell::new(0),
+};
+
+critical_section::set_impl!(EspIdfCriticalSection);
+
+unsafe impl critical_section::Impl for EspIdfCriticalSection {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        let guard = LOCK.enter();
+
+        let depth = &mut *HELD.depth.get();
+        *depth += 1;
+        match *depth {
+            1 => *HELD.guard.get() = Some(guard),
+            // still held by the outer one, this only undoes the nesting
+            _ => drop(guard),
+        }
+    }
+
+    unsafe fn release(_: critical_section::RawRestoreState) {
+        let depth = &mut *HELD.depth.get();
+        *depth -= 1;
+        if *depth == 0 {
+            drop((*HELD.guard.get()).take());
+        }
+    }
+}
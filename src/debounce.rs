@@ -1,9 +1,41 @@
-use esp_idf_sys::esp_timer_get_time;
+use crate::timer::EspTimer;
+use esp_idf_sys::{
+    esp_rom_delay_us, esp_timer_get_time, esp_timer_init, EspError, ESP_ERR_INVALID_STATE, ESP_OK,
+};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 /// A debouncer to resolve interrupt debouncing issues
 pub trait Debounce {
     fn is_isr_valid(&mut self) -> bool;
+
+    /// Same as `is_isr_valid`, but also told where the edge goes (`true` = to the active level),
+    /// for debouncers that treat presses and releases differently. Defaults to `is_isr_valid`.
+    #[inline(always)]
+    fn is_edge_valid(&mut self, _active: bool) -> bool {
+        self.is_isr_valid()
+    }
+
+    /// Raise the debounce window to `temporary` for the next `for_`, then go back to normal.
+    /// Debouncers without a window to raise can ignore it, which is the default.
+    fn boost(&mut self, _temporary: Duration, _for_: Duration) {}
+
+    /// For debouncers that confirm an edge by polling the pin instead of timing the edges (`PollDebounce`):
+    /// the sample interval and the number of identical reads in a row needed. `None` by default.
+    fn poll_confirm(&self) -> Option<(Duration, u32)> {
+        None
+    }
+
+    /// Start over as if no edge was seen yet. Called by the handle with its interrupt off, right before
+    /// it starts from a fresh level (`arm`, `reassert`, `reconfigure`). Nothing to do by default.
+    fn reset(&mut self) {}
+
+    /// A short name of the debouncing strategy for logs and diagnostics dumps, `"custom"` by default
+    fn strategy_name(&self) -> &'static str {
+        "custom"
+    }
 }
 
 /// A debouncer that does nothing
@@ -13,25 +45,99 @@ impl Debounce for NoDebounce {
     fn is_isr_valid(&mut self) -> bool {
         true
     }
+
+    fn strategy_name(&self) -> &'static str {
+        "none"
+    }
 }
 
-fn micros() -> i64 {
+/// All the debouncers (and the edge timestamps) are based on `esp_timer`,
+/// which has to be running for any of them to work, see `check_timer`
+pub(crate) fn micros() -> i64 {
     unsafe { esp_timer_get_time() }
 }
 
+/// The longest window a debouncer (or anything else timing edges) takes, longer ones are clamped to it
+/// A century: far beyond any sensible setting, and far enough from `i64::MAX` that adding it to
+/// an `esp_timer` time can't overflow, so a nonsense value like `Duration::MAX` still gives a working window.
+pub const MAX_WINDOW: Duration = Duration::from_secs(100 * 365 * 24 * 3600);
+
+/// `duration` in the microseconds the windows are compared in, clamped to `MAX_WINDOW`
+pub(crate) fn window_micros(duration: Duration) -> i64 {
+    duration.min(MAX_WINDOW).as_micros() as i64
+}
+
+static TIMER_CHECKED: AtomicBool = AtomicBool::new(false);
+
+/// Make sure `esp_timer` is running, otherwise `micros` is stuck and no edge is ever debounced.
+/// It is started by the esp-idf startup code on every normal app, minimal apps may skip it,
+/// in which case it is initialized here. Fails with `ESP_ERR_INVALID_STATE` if time still doesn't advance.
+/// Only checked once, by the first handle created.
+pub(crate) fn check_timer() -> Result<(), EspError> {
+    if TIMER_CHECKED.load(Ordering::Acquire) {
+        return Ok(());
+    }
+
+    if !timer_advances() {
+        match unsafe { esp_timer_init() } {
+            ESP_OK | ESP_ERR_INVALID_STATE => {}
+            err => return Err(EspError::from(err).unwrap()),
+        }
+
+        if !timer_advances() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+        }
+    }
+
+    TIMER_CHECKED.store(true, Ordering::Release);
+    Ok(())
+}
+
+fn timer_advances() -> bool {
+    let before = micros();
+    unsafe { esp_rom_delay_us(10) };
+
+    micros() > before
+}
+
+/// A temporarily raised debounce window, shared by the debouncers that support `Debounce::boost`
+#[derive(Default)]
+struct Boost {
+    window: i64,
+    until: i64,
+}
+
+impl Boost {
+    fn set(&mut self, temporary: Duration, for_: Duration) {
+        self.window = window_micros(temporary);
+        self.until = micros() + window_micros(for_);
+    }
+
+    /// The window to use at `now`, never lower than the normal one
+    #[inline(always)]
+    fn window(&self, normal: i64, now: i64) -> i64 {
+        match now < self.until {
+            true => normal.max(self.window),
+            false => normal,
+        }
+    }
+}
+
 /// classic debounce just waits for a certain amount of time to pass
 /// between interrupts, so it's not very accurate but works for a lot of cases.
 /// most of the time, a 5 to 20 ms debounce time is enough.
 pub struct ClassicDebounce {
     debounce_time: i64,
     last_sample: i64,
+    boost: Boost,
 }
 
 impl ClassicDebounce {
     pub fn new(debounce_time: Duration) -> Self {
         Self {
-            debounce_time: debounce_time.as_micros() as i64,
+            debounce_time: window_micros(debounce_time),
             last_sample: micros(),
+            boost: Boost::default(),
         }
     }
 }
@@ -39,13 +145,137 @@ impl ClassicDebounce {
 impl Debounce for ClassicDebounce {
     fn is_isr_valid(&mut self) -> bool {
         let now = micros();
-        if now - self.last_sample < self.debounce_time {
+        if now - self.last_sample < self.boost.window(self.debounce_time, now) {
             return false;
         }
 
         self.last_sample = now;
         true
     }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.boost.set(temporary, for_);
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "classic"
+    }
+}
+
+/// The configuration half of a `SharedConfigDebounce`, shared by all the pins that debounce alike
+pub struct DebounceTiming {
+    debounce_time: i64,
+}
+
+impl DebounceTiming {
+    pub fn new(debounce_time: Duration) -> Self {
+        Self {
+            debounce_time: window_micros(debounce_time),
+        }
+    }
+}
+
+/// A `ClassicDebounce` split in two: the timing is shared (an `Rc`, or a `&'static` for a timing
+/// in a static), only the time of the last edge is kept per pin. For a bank of identical switches
+/// to be sure they all debounce the same, e.g. with `LeveledEdge::new_bank`:
+/// `|| SharedConfigDebounce::new(timing.clone())`.
+/// The timing is read only, so unlike `ClassicDebounce` it can't be boosted.
+pub struct SharedConfigDebounce<Timing = Rc<DebounceTiming>>
+where
+    Timing: Deref<Target = DebounceTiming>,
+{
+    timing: Timing,
+    last_sample: i64,
+}
+
+impl<Timing> SharedConfigDebounce<Timing>
+where
+    Timing: Deref<Target = DebounceTiming>,
+{
+    pub fn new(timing: Timing) -> Self {
+        Self {
+            timing,
+            last_sample: micros(),
+        }
+    }
+}
+
+impl<Timing> Debounce for SharedConfigDebounce<Timing>
+where
+    Timing: Deref<Target = DebounceTiming>,
+{
+    #[inline(always)]
+    fn is_isr_valid(&mut self) -> bool {
+        let now = micros();
+        if now - self.last_sample < self.timing.debounce_time {
+            return false;
+        }
+
+        self.last_sample = now;
+        true
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "classic"
+    }
+}
+
+/// Act on the first edge right away, then ignore everything for `lockout` (the bounce tail)
+///
+/// For triggers where latency matters more than anything (a shutter release): the edge that starts
+/// a bounce is delivered as soon as the handler sees it, nothing is waited for before deciding.
+/// `ClassicDebounce` times its window the same way, from the last accepted edge, but it starts timing
+/// when it is created, so an edge right after arming can be lost. This one lets the first edge
+/// after arming (or `Debounce::reset`) through no matter what.
+/// Unlike gap based debouncers (`FilterDebounce`), an edge is never held back to see if the pin stays quiet,
+/// which also means a single glitch is delivered like a real press.
+/// The level the pin ends up at after the lockout is still caught up on by the level interrupt.
+pub struct LeadingEdgeDebounce {
+    lockout: i64,
+    last: Option<i64>,
+    boost: Boost,
+}
+
+impl LeadingEdgeDebounce {
+    pub fn new(lockout: Duration) -> Self {
+        Self {
+            lockout: window_micros(lockout),
+            last: None,
+            boost: Boost::default(),
+        }
+    }
+
+    /// `is_isr_valid` for an edge at `now`
+    #[inline(always)]
+    fn is_valid_at(&mut self, now: i64) -> bool {
+        if let Some(last) = self.last {
+            if now - last < self.boost.window(self.lockout, now) {
+                return false;
+            }
+        }
+
+        self.last = Some(now);
+        true
+    }
+}
+
+impl Debounce for LeadingEdgeDebounce {
+    #[inline(always)]
+    fn is_isr_valid(&mut self) -> bool {
+        self.is_valid_at(micros())
+    }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.boost.set(temporary, for_);
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "leading_edge"
+    }
 }
 
 /// A debouncer for special cases when the pin can have small glitches when it is not bouncing
@@ -54,14 +284,16 @@ pub struct FilterDebounce {
     debounce_time: i64,
     last_sample: i64,
     ignore_next: bool,
+    boost: Boost,
 }
 
 impl FilterDebounce {
     pub fn new(debounce_time: Duration) -> Self {
         Self {
-            debounce_time: debounce_time.as_micros() as i64,
+            debounce_time: window_micros(debounce_time),
             last_sample: micros(),
             ignore_next: false,
+            boost: Boost::default(),
         }
     }
 }
@@ -74,7 +306,7 @@ impl Debounce for FilterDebounce {
         let now = micros();
 
         if !self.ignore_next {
-            if now - self.last_sample < self.debounce_time {
+            if now - self.last_sample < self.boost.window(self.debounce_time, now) {
                 self.ignore_next = true;
             } else {
                 is_passed = true;
@@ -87,4 +319,670 @@ impl Debounce for FilterDebounce {
 
         is_passed
     }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.boost.set(temporary, for_);
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "filter"
+    }
+}
+
+/// A debouncer for force sensitive or capacitive buttons, where holding the button is noisy too
+/// The press is debounced with the usual `press_window`, but once pressed every edge that comes
+/// within `hold_window` of the previous one (accepted or not) is taken as noise of the hold,
+/// so the release is only accepted after the pin was quiet for the whole `hold_window`.
+pub struct HoldAwareDebounce {
+    press_window: i64,
+    hold_window: i64,
+    last_sample: i64,
+    active: bool,
+    boost: Boost,
+}
+
+impl HoldAwareDebounce {
+    pub fn new(press_window: Duration, hold_window: Duration) -> Self {
+        Self::starting_at(press_window, hold_window, micros())
+    }
+
+    fn starting_at(press_window: Duration, hold_window: Duration, now: i64) -> Self {
+        Self {
+            press_window: window_micros(press_window),
+            hold_window: window_micros(hold_window),
+            last_sample: now,
+            active: false,
+            boost: Boost::default(),
+        }
+    }
+
+    /// `is_edge_valid` for an edge at `now`
+    #[inline(always)]
+    fn is_edge_valid_at(&mut self, active: bool, now: i64) -> bool {
+        let since_last = now - self.last_sample;
+
+        let is_passed = match (self.active, active) {
+            (false, true) => since_last >= self.boost.window(self.press_window, now),
+            (true, false) => since_last >= self.boost.window(self.hold_window, now),
+            // same direction as the accepted state, nothing changed
+            _ => false,
+        };
+
+        // while held, every edge pushes the release further away
+        if is_passed || self.active {
+            self.last_sample = now;
+        }
+
+        if is_passed {
+            self.active = active;
+        }
+
+        is_passed
+    }
+}
+
+impl Debounce for HoldAwareDebounce {
+    /// Without the level, assume the edge goes the other way from the last accepted one
+    fn is_isr_valid(&mut self) -> bool {
+        self.is_edge_valid(!self.active)
+    }
+
+    #[inline(always)]
+    #[link_section = ".iram1.hold_aware_bouncer"]
+    fn is_edge_valid(&mut self, active: bool) -> bool {
+        self.is_edge_valid_at(active, micros())
+    }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.boost.set(temporary, for_);
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "hold_aware"
+    }
+}
+
+/// A classic debounce that gets out of the way of deliberate rapid pressing (button mashing)
+///
+/// Presses that come regularly, each one less than `MASH_FACTOR` debounce windows after the previous one
+/// and within `max_jitter` of the previous interval, are counted as intentional.
+/// After `presses` of them in a row the window drops to `rapid_time` until the rhythm breaks.
+/// Bounces are rejected as usual, they come well inside the window and nowhere near regular.
+pub struct RapidPressDebounce {
+    debounce_time: i64,
+    rapid_time: i64,
+    presses: u32,
+    max_jitter: i64,
+    last_sample: i64,
+    last_press: i64,
+    last_interval: i64,
+    streak: u32,
+    boost: Boost,
+}
+
+impl RapidPressDebounce {
+    const MASH_FACTOR: i64 = 4;
+
+    /// debounce_time: The normal window, as in `ClassicDebounce`
+    /// rapid_time: The window while the user is mashing, shorter than `debounce_time`
+    /// presses: Regular presses in a row needed to switch to `rapid_time`, lower is more sensitive
+    /// max_jitter: How far a press interval may be from the previous one to still count as regular
+    pub fn new(
+        debounce_time: Duration,
+        rapid_time: Duration,
+        presses: u32,
+        max_jitter: Duration,
+    ) -> Self {
+        Self::starting_at(debounce_time, rapid_time, presses, max_jitter, micros())
+    }
+
+    fn starting_at(
+        debounce_time: Duration,
+        rapid_time: Duration,
+        presses: u32,
+        max_jitter: Duration,
+        now: i64,
+    ) -> Self {
+        Self {
+            debounce_time: window_micros(debounce_time),
+            rapid_time: window_micros(rapid_time),
+            presses,
+            max_jitter: window_micros(max_jitter),
+            last_sample: now,
+            last_press: now,
+            last_interval: 0,
+            streak: 0,
+            boost: Boost::default(),
+        }
+    }
+
+    /// Whether the window is lowered at the moment for mashing
+    pub fn is_rapid(&self) -> bool {
+        self.is_rapid_at(micros())
+    }
+
+    #[inline(always)]
+    fn is_rapid_at(&self, now: i64) -> bool {
+        self.streak >= self.presses
+            && now - self.last_press < Self::MASH_FACTOR * self.debounce_time
+    }
+
+    #[inline(always)]
+    fn track_press(&mut self, now: i64) {
+        let interval = now - self.last_press;
+        let regular = interval < Self::MASH_FACTOR * self.debounce_time
+            && (interval - self.last_interval).abs() <= self.max_jitter;
+
+        self.streak = match regular {
+            true => self.streak.saturating_add(1),
+            false => 0,
+        };
+        self.last_interval = interval;
+        self.last_press = now;
+    }
+
+    /// `is_edge_valid` for an edge at `now`
+    #[inline(always)]
+    fn is_edge_valid_at(&mut self, active: bool, now: i64) -> bool {
+        let window = match self.is_rapid_at(now) {
+            true => self.rapid_time,
+            false => self.debounce_time,
+        };
+
+        if now - self.last_sample < self.boost.window(window, now) {
+            return false;
+        }
+
+        self.last_sample = now;
+        if active {
+            self.track_press(now);
+        }
+
+        true
+    }
+}
+
+impl Debounce for RapidPressDebounce {
+    /// Without the level every edge is taken as a press
+    fn is_isr_valid(&mut self) -> bool {
+        self.is_edge_valid(true)
+    }
+
+    #[inline(always)]
+    #[link_section = ".iram1.rapid_press_bouncer"]
+    fn is_edge_valid(&mut self, active: bool) -> bool {
+        self.is_edge_valid_at(active, micros())
+    }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.boost.set(temporary, for_);
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "rapid_press"
+    }
+}
+
+/// A preset for reed switches, which bounce on closing and can close a second time
+/// when the magnet wobbles past them (a door bouncing in its frame, a wheel magnet passing at an angle)
+///
+/// Make the closed level the active one. A closure needs `close_window` of quiet since the last accepted
+/// edge, and no closure is accepted for `lockout` after an open, which swallows the secondary closure.
+/// Opening barely bounces, it only needs the shorter `open_window`.
+/// `ReedDebounce::new()` has values that work for the usual glass reed switches.
+pub struct ReedDebounce {
+    close_window: i64,
+    open_window: i64,
+    lockout: i64,
+    last_sample: i64,
+    last_open: i64,
+    closed: bool,
+    boost: Boost,
+}
+
+impl ReedDebounce {
+    pub const CLOSE_WINDOW: Duration = Duration::from_millis(10);
+    pub const OPEN_WINDOW: Duration = Duration::from_millis(3);
+    pub const LOCKOUT: Duration = Duration::from_millis(50);
+
+    /// With `CLOSE_WINDOW`, `OPEN_WINDOW` and `LOCKOUT`
+    pub fn new() -> Self {
+        Self::with_windows(Self::CLOSE_WINDOW, Self::OPEN_WINDOW, Self::LOCKOUT)
+    }
+
+    /// close_window: Quiet time needed before a closure is accepted
+    /// open_window: Quiet time needed before an open is accepted, shorter than `close_window`
+    /// lockout: How long after an open a new closure is ignored, longer than `close_window`
+    pub fn with_windows(close_window: Duration, open_window: Duration, lockout: Duration) -> Self {
+        Self::starting_at(close_window, open_window, lockout, micros())
+    }
+
+    fn starting_at(
+        close_window: Duration,
+        open_window: Duration,
+        lockout: Duration,
+        now: i64,
+    ) -> Self {
+        Self {
+            close_window: window_micros(close_window),
+            open_window: window_micros(open_window),
+            lockout: window_micros(lockout),
+            last_sample: now,
+            last_open: now - window_micros(lockout),
+            closed: false,
+            boost: Boost::default(),
+        }
+    }
+
+    /// `is_edge_valid` for an edge at `now`
+    #[inline(always)]
+    fn is_edge_valid_at(&mut self, active: bool, now: i64) -> bool {
+        let since_last = now - self.last_sample;
+
+        let is_passed = match (self.closed, active) {
+            (false, true) => {
+                since_last >= self.boost.window(self.close_window, now)
+                    && now - self.last_open >= self.lockout
+            }
+            (true, false) => since_last >= self.boost.window(self.open_window, now),
+            // same direction as the accepted state, nothing changed
+            _ => false,
+        };
+
+        if !is_passed {
+            return false;
+        }
+
+        self.last_sample = now;
+        self.closed = active;
+        if !active {
+            self.last_open = now;
+        }
+
+        true
+    }
+}
+
+impl Default for ReedDebounce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debounce for ReedDebounce {
+    /// Without the level, assume the edge goes the other way from the last accepted one
+    fn is_isr_valid(&mut self) -> bool {
+        self.is_edge_valid(!self.closed)
+    }
+
+    #[inline(always)]
+    #[link_section = ".iram1.reed_bouncer"]
+    fn is_edge_valid(&mut self, active: bool) -> bool {
+        self.is_edge_valid_at(active, micros())
+    }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.boost.set(temporary, for_);
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "reed"
+    }
+}
+
+/// The software poll debounce of many Arduino libraries: the first edge turns the interrupt off
+/// and starts sampling the pin every `sample_interval`, the edge is only accepted once the pin read the same
+/// `required_stable` times in a row, and then the level interrupt is armed again.
+///
+/// It doesn't care how long the contact bounces, at the cost of latency
+/// (at least `sample_interval * required_stable` after the bouncing stopped).
+/// The sampling runs on an `esp_timer`, so the callback of a handle with a `PollDebounce`
+/// is called from the esp_timer task and not from the isr.
+pub struct PollDebounce {
+    sample_interval: Duration,
+    required_stable: u32,
+}
+
+impl PollDebounce {
+    pub fn new(sample_interval: Duration, required_stable: u32) -> Self {
+        Self {
+            sample_interval,
+            required_stable: required_stable.max(1),
+        }
+    }
+}
+
+impl Debounce for PollDebounce {
+    /// The polling already did the debouncing by the time this is asked
+    fn is_isr_valid(&mut self) -> bool {
+        true
+    }
+
+    fn poll_confirm(&self) -> Option<(Duration, u32)> {
+        Some((self.sample_interval, self.required_stable))
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "poll"
+    }
+}
+
+/// Rejects the first `skip_count` edges after the handle is armed, then hands every edge to `inner`
+///
+/// For boards with a known burst of spurious edges right after the interrupt is enabled
+/// (pulls charging the line, a pin that was just reconfigured), where counting them off is more
+/// predictable than guessing an `arming_delay`. The count starts over on every `Debounce::reset`,
+/// i.e. when the handle is armed again. Wraps any other debouncer, `NoDebounce` for none.
+/// The edges it skips are still tracked, only not delivered, so the state stays right.
+pub struct WarmupDebounce<Inner>
+where
+    Inner: Debounce,
+{
+    inner: Inner,
+    skip_count: u32,
+    remaining: u32,
+}
+
+impl<Inner> WarmupDebounce<Inner>
+where
+    Inner: Debounce,
+{
+    pub fn new(skip_count: u32, inner: Inner) -> Self {
+        Self {
+            inner,
+            skip_count,
+            remaining: skip_count,
+        }
+    }
+
+    #[inline(always)]
+    fn skip(&mut self) -> bool {
+        match self.remaining {
+            0 => false,
+            _ => {
+                self.remaining -= 1;
+                true
+            }
+        }
+    }
+}
+
+impl<Inner> Debounce for WarmupDebounce<Inner>
+where
+    Inner: Debounce,
+{
+    #[inline(always)]
+    fn is_isr_valid(&mut self) -> bool {
+        !self.skip() && self.inner.is_isr_valid()
+    }
+
+    #[inline(always)]
+    fn is_edge_valid(&mut self, active: bool) -> bool {
+        !self.skip() && self.inner.is_edge_valid(active)
+    }
+
+    fn boost(&mut self, temporary: Duration, for_: Duration) {
+        self.inner.boost(temporary, for_);
+    }
+
+    fn poll_confirm(&self) -> Option<(Duration, u32)> {
+        self.inner.poll_confirm()
+    }
+
+    fn reset(&mut self) {
+        self.remaining = self.skip_count;
+        self.inner.reset();
+    }
+
+    fn strategy_name(&self) -> &'static str {
+        "warmup"
+    }
+}
+
+/// The counting of a polling debouncer, the reads in a row at the same level
+struct StableReads {
+    required: u32,
+    stable: u32,
+    level: bool,
+}
+
+impl StableReads {
+    fn new(required: u32) -> Self {
+        Self {
+            required,
+            stable: 0,
+            level: false,
+        }
+    }
+
+    #[inline(always)]
+    fn start(&mut self, level: bool) {
+        self.level = level;
+        self.stable = 0;
+    }
+
+    /// Returns the level once it was read the same `required` times in a row
+    fn sample(&mut self, level: bool) -> Option<bool> {
+        match level == self.level {
+            true => self.stable += 1,
+            false => {
+                self.level = level;
+                self.stable = 1;
+            }
+        }
+
+        (self.stable >= self.required).then_some(level)
+    }
+}
+
+/// The sampling of a handle with a polling debouncer, see `Debounce::poll_confirm`
+pub(crate) struct Poller {
+    timer: EspTimer,
+    interval_us: u64,
+    reads: StableReads,
+}
+
+impl Poller {
+    pub(crate) fn new(timer: EspTimer, interval: Duration, required_stable: u32) -> Self {
+        Self {
+            timer,
+            interval_us: window_micros(interval) as u64,
+            reads: StableReads::new(required_stable),
+        }
+    }
+
+    /// Called from the isr on the first edge, the interrupt stays off while sampling
+    #[inline(always)]
+    pub(crate) fn start(&mut self, level: bool) {
+        self.reads.start(level);
+        self.timer.start_periodic_from_isr(self.interval_us);
+    }
+
+    /// Take a read of the pin, returns the level once it was the same `required_stable` times in a row
+    /// and stops sampling
+    pub(crate) fn sample(&mut self, level: bool) -> Option<bool> {
+        let settled = self.reads.sample(level)?;
+
+        self.timer.stop();
+        Some(settled)
+    }
+
+    /// Stop sampling and wait for a sample that is running right now, see `EspTimer::stop_and_wait`
+    pub(crate) fn pause(&self) {
+        self.timer.stop_and_wait();
+    }
+
+    /// Undo `pause`, and go on sampling if it was in the middle of it
+    pub(crate) fn resume(&self, sampling: bool) {
+        self.timer.reopen();
+        if sampling {
+            self.timer.start_periodic_from_isr(self.interval_us);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: i64 = 1000;
+
+    #[test]
+    fn window_of_nonsense_durations() {
+        assert_eq!(window_micros(Duration::from_millis(20)), 20 * MS);
+        assert_eq!(window_micros(Duration::MAX), window_micros(MAX_WINDOW));
+        assert!(window_micros(Duration::MAX) > 0);
+        // far from overflowing when added to a timestamp
+        assert!(window_micros(Duration::MAX) < i64::MAX / 1000);
+
+        let mut debouncer = LeadingEdgeDebounce::new(Duration::MAX);
+        assert!(debouncer.is_valid_at(0));
+        assert!(!debouncer.is_valid_at(365 * 24 * 3600 * 1000 * MS));
+    }
+
+    #[test]
+    fn leading_edge_acts_then_suppresses() {
+        let mut debouncer = LeadingEdgeDebounce::new(Duration::from_millis(20));
+
+        // the very first edge goes through as it comes, nothing to wait for
+        assert!(debouncer.is_valid_at(100 * MS));
+        // its bounce tail
+        assert!(!debouncer.is_valid_at(100 * MS + 200));
+        assert!(!debouncer.is_valid_at(105 * MS));
+        assert!(!debouncer.is_valid_at(119 * MS));
+        // the next real edge, once the lockout is over
+        assert!(debouncer.is_valid_at(120 * MS));
+        assert!(!debouncer.is_valid_at(121 * MS));
+    }
+
+    #[test]
+    fn reed_trace() {
+        let mut reed = ReedDebounce::starting_at(
+            ReedDebounce::CLOSE_WINDOW,
+            ReedDebounce::OPEN_WINDOW,
+            ReedDebounce::LOCKOUT,
+            0,
+        );
+
+        // the magnet comes close, the contacts bounce on closing
+        assert!(reed.is_edge_valid_at(true, 20 * MS));
+        assert!(!reed.is_edge_valid_at(false, 20 * MS + 300));
+        assert!(!reed.is_edge_valid_at(true, 20 * MS + 600));
+        assert!(!reed.is_edge_valid_at(false, 21 * MS));
+        assert!(!reed.is_edge_valid_at(true, 22 * MS));
+
+        // it goes away, opening barely bounces
+        assert!(reed.is_edge_valid_at(false, 500 * MS));
+        assert!(!reed.is_edge_valid_at(true, 500 * MS + 500));
+        assert!(!reed.is_edge_valid_at(false, 501 * MS));
+
+        // the wobble closes it again past the close window, inside the lockout
+        assert!(!reed.is_edge_valid_at(true, 530 * MS));
+        assert!(!reed.is_edge_valid_at(false, 535 * MS));
+
+        // the next real closure
+        assert!(reed.is_edge_valid_at(true, 600 * MS));
+    }
+
+    #[test]
+    fn hold_noise_is_not_a_release() {
+        let mut button = HoldAwareDebounce::starting_at(
+            Duration::from_millis(20),
+            Duration::from_millis(200),
+            0,
+        );
+
+        assert!(button.is_edge_valid_at(true, 100 * MS));
+        // the finger shifts while holding, the pad lets go and comes back
+        assert!(!button.is_edge_valid_at(false, 150 * MS));
+        assert!(!button.is_edge_valid_at(true, 151 * MS));
+        assert!(!button.is_edge_valid_at(false, 300 * MS));
+
+        // quiet for the whole hold window since the last of the noise
+        assert!(button.is_edge_valid_at(false, 600 * MS));
+        // and the press after it is debounced with the press window again
+        assert!(!button.is_edge_valid_at(true, 610 * MS));
+        assert!(button.is_edge_valid_at(true, 700 * MS));
+    }
+
+    fn mashing() -> RapidPressDebounce {
+        RapidPressDebounce::starting_at(
+            Duration::from_millis(30),
+            Duration::from_millis(10),
+            3,
+            Duration::from_millis(5),
+            0,
+        )
+    }
+
+    #[test]
+    fn bounce_bursts_stay_rejected() {
+        let mut button = mashing();
+
+        // presses a second apart, each bouncing a few times on contact
+        for press in 1..=5 {
+            let at = press * 1000 * MS;
+            assert!(button.is_edge_valid_at(true, at));
+            for bounce in [1, 3, 6, 9] {
+                assert!(!button.is_edge_valid_at(bounce % 2 == 0, at + bounce * MS));
+            }
+            assert!(button.is_edge_valid_at(false, at + 200 * MS));
+        }
+
+        assert!(!button.is_rapid_at(5200 * MS));
+    }
+
+    #[test]
+    fn mashing_lowers_the_window() {
+        let mut button = mashing();
+
+        // pressed every 60ms, released halfway, as regular as a thumb gets
+        let mut at = 1000 * MS;
+        for _ in 0..5 {
+            assert!(button.is_edge_valid_at(true, at));
+            assert!(button.is_edge_valid_at(false, at + 30 * MS));
+            at += 60 * MS;
+        }
+        assert!(button.is_rapid_at(at));
+
+        // now a release 15ms after the press gets through, too quick for the normal window
+        assert!(button.is_edge_valid_at(true, at));
+        assert!(button.is_edge_valid_at(false, at + 15 * MS));
+        // the bounces still don't, they come well inside even the rapid window
+        assert!(!button.is_edge_valid_at(true, at + 17 * MS));
+
+        // the rhythm breaks, back to the normal window
+        let at = at + 1000 * MS;
+        assert!(button.is_edge_valid_at(true, at));
+        assert!(!button.is_rapid_at(at));
+        assert!(!button.is_edge_valid_at(false, at + 15 * MS));
+    }
+
+    #[test]
+    fn poll_waits_out_the_bounce() {
+        // a press bouncing for 10ms, read every 1ms, 3 reads in a row to confirm
+        let mut reads = StableReads::new(3);
+        reads.start(true);
+
+        let bounce = [false, true, true, false, true, false, true, true, false];
+        for level in bounce {
+            assert_eq!(reads.sample(level), None);
+        }
+
+        // settled high from 10ms on, confirmed on the third read
+        assert_eq!(reads.sample(true), None);
+        assert_eq!(reads.sample(true), None);
+        assert_eq!(reads.sample(true), Some(true));
+    }
+
+    #[test]
+    fn poll_reports_a_bounce_back() {
+        let mut reads = StableReads::new(3);
+        reads.start(true);
+
+        assert_eq!(reads.sample(true), None);
+        assert_eq!(reads.sample(false), None);
+        assert_eq!(reads.sample(false), None);
+        assert_eq!(reads.sample(false), Some(false));
+    }
 }
@@ -0,0 +1,302 @@
+use crate::builder::LeveledEdgeBuilder;
+use crate::debounce::Debounce;
+use crate::leveled_edge::LeveledEdge;
+use crate::queue::IsrQueue;
+use crate::state::HoldOff;
+use esp_idf_hal::delay::TickType;
+use esp_idf_hal::gpio::{GpioPin, Input, Pin};
+#[cfg(feature = "task_wdt")]
+use esp_idf_sys::ESP_OK;
+use esp_idf_sys::{
+    c_types::c_void, EspError, TaskHandle_t, TickType_t, ESP_ERR_INVALID_ARG, ESP_ERR_NO_MEM,
+};
+use std::ptr;
+use std::sync::Arc;
+
+/// The isr side callback of a deferred handle, it only queues the edge for the task
+pub type DeferredCallback = Box<dyn FnMut(bool)>;
+
+type TaskCallback<T> = Box<dyn FnMut(T) + Send>;
+
+/// Wait forever, `portMAX_DELAY`
+const BLOCK: TickType = TickType(TickType_t::MAX);
+
+/// The smallest stack of a task in bytes (esp-idf counts stacks in bytes), no task of the crate
+/// may get less. From the sdkconfig, it follows `CONFIG_FREERTOS_IDLE_TASK_STACKSIZE`
+const MIN_TASK_STACK: u32 = esp_idf_sys::configMINIMAL_STACK_SIZE;
+/// Task priorities go up to one less, from the FreeRTOS config of esp-idf
+const MAX_TASK_PRIORITIES: u32 = esp_idf_sys::configMAX_PRIORITIES;
+
+#[derive(Clone, Copy)]
+enum Event<T> {
+    Edge(T),
+    Stop,
+}
+
+/// The task watchdog of the deferred task, subscribed for its whole life with the `task_wdt` feature
+/// The task is fed after every callback and at least every `FEED_PERIOD` while idle,
+/// so only a callback that hangs for longer than the TWDT timeout trips it.
+#[cfg(feature = "task_wdt")]
+struct Watchdog {
+    /// `false` if the TWDT isn't running (or is full), the task then runs unwatched
+    subscribed: bool,
+}
+
+#[cfg(feature = "task_wdt")]
+impl Watchdog {
+    /// Well within the 5s default of `CONFIG_ESP_TASK_WDT_TIMEOUT_S`
+    const FEED_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+
+    /// Subscribe the calling task
+    fn subscribe() -> Self {
+        let subscribed = unsafe { esp_idf_sys::esp_task_wdt_add(ptr::null_mut()) } == ESP_OK;
+        Self { subscribed }
+    }
+
+    /// How long the task may block on its queue before it has to come back and feed
+    fn timeout(&self) -> TickType {
+        match self.subscribed {
+            true => Self::FEED_PERIOD.into(),
+            false => BLOCK,
+        }
+    }
+
+    fn feed(&self) {
+        if self.subscribed {
+            unsafe { esp_idf_sys::esp_task_wdt_reset() };
+        }
+    }
+
+    /// A deleted task that is still subscribed would trip the watchdog
+    fn unsubscribe(self) {
+        if self.subscribed {
+            unsafe { esp_idf_sys::esp_task_wdt_delete(ptr::null_mut()) };
+        }
+    }
+}
+
+/// Without the `task_wdt` feature the task isn't subscribed to the TWDT and blocks on its queue for good
+#[cfg(not(feature = "task_wdt"))]
+struct Watchdog;
+
+#[cfg(not(feature = "task_wdt"))]
+impl Watchdog {
+    fn subscribe() -> Self {
+        Self
+    }
+
+    fn timeout(&self) -> TickType {
+        BLOCK
+    }
+
+    fn feed(&self) {}
+
+    fn unsubscribe(self) {}
+}
+
+/// What the task gets, boxed and handed over as the task argument
+struct TaskArgs<T: Copy> {
+    queue: Arc<IsrQueue<Event<T>>>,
+    callback: TaskCallback<T>,
+    hold_off: Option<Arc<HoldOff>>,
+}
+
+/// Enables the interrupt held off for an edge once it's handled, whichever way the handling ends
+struct Release<'a>(&'a Option<Arc<HoldOff>>);
+
+impl Drop for Release<'_> {
+    fn drop(&mut self) {
+        if let Some(hold_off) = self.0 {
+            hold_off.release();
+        }
+    }
+}
+
+/// The task a deferred handle calls its callback from, stopped when the handle is dropped
+/// `T` is what the isr hands it, the level of the edge for a deferred handle, an `EdgeEvent` for `Sink::deferred`.
+pub(crate) struct DeferredTask<T: Copy + Send + 'static = bool> {
+    queue: Arc<IsrQueue<Event<T>>>,
+    hold_off: Option<Arc<HoldOff>>,
+}
+
+impl<T: Copy + Send + 'static> DeferredTask<T> {
+    const QUEUE_LENGTH: u32 = 16;
+    const NAME: &'static [u8] = b"leveled_edge\0";
+
+    /// Fails with `ESP_ERR_INVALID_ARG` if the stack or the priority is out of what FreeRTOS takes,
+    /// and with `ESP_ERR_NO_MEM` if the task couldn't be created
+    /// hold_off: Set for a handle with backpressure, the interrupt is left off until the edge was handled
+    pub(crate) fn spawn(
+        stack: u32,
+        prio: u32,
+        callback: TaskCallback<T>,
+        hold_off: Option<Arc<HoldOff>>,
+    ) -> Result<Self, EspError> {
+        if stack < MIN_TASK_STACK || prio >= MAX_TASK_PRIORITIES {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        let queue = Arc::new(
+            IsrQueue::new(Self::QUEUE_LENGTH)
+                .ok_or_else(|| EspError::from(ESP_ERR_NO_MEM).unwrap())?,
+        );
+
+        let args = Box::into_raw(Box::new(TaskArgs {
+            queue: queue.clone(),
+            callback,
+            hold_off: hold_off.clone(),
+        }));
+        let mut handle: TaskHandle_t = ptr::null_mut();
+
+        let created = unsafe {
+            esp_idf_sys::xTaskCreatePinnedToCore(
+                Some(Self::run),
+                Self::NAME.as_ptr() as *const _,
+                stack,
+                args as *mut c_void,
+                prio,
+                &mut handle,
+                esp_idf_sys::tskNO_AFFINITY as _,
+            )
+        };
+
+        if created != 1 {
+            drop(unsafe { Box::from_raw(args) });
+            return Err(EspError::from(ESP_ERR_NO_MEM).unwrap());
+        }
+
+        Ok(Self { queue, hold_off })
+    }
+
+    pub(crate) fn hold_off(&self) -> Option<Arc<HoldOff>> {
+        self.hold_off.clone()
+    }
+
+    /// Queue `item` for the task from the isr, returns `false` if the queue was full and it was dropped
+    #[inline(always)]
+    pub(crate) fn send_from_isr(&self, item: T) -> bool {
+        self.queue.send_from_isr(Event::Edge(item))
+    }
+
+    unsafe extern "C" fn run(args: *mut c_void) {
+        let TaskArgs {
+            queue,
+            mut callback,
+            hold_off,
+        } = *Box::from_raw(args as *mut TaskArgs<T>);
+        let watchdog = Watchdog::subscribe();
+
+        loop {
+            match queue.recv(watchdog.timeout()) {
+                Some(Event::Edge(state)) => {
+                    let _release = Release(&hold_off);
+                    callback(state);
+                }
+                Some(Event::Stop) => break,
+                None => {}
+            }
+            watchdog.feed();
+        }
+
+        // the callback and the queue have to go before the task deletes itself,
+        // and it has to be off the watchdog
+        watchdog.unsubscribe();
+        drop(callback);
+        drop(queue);
+        drop(hold_off);
+        esp_idf_sys::vTaskDelete(ptr::null_mut());
+    }
+}
+
+impl DeferredTask {
+    /// The isr side of the handle, queue the edge and let the task take it from there
+    /// With backpressure the interrupt is held off before the edge is queued, the task (maybe on
+    /// the other core) could otherwise release it before it was held and leave it off for good.
+    /// If the queue was full the hold is dropped again, no task would ever enable it.
+    fn isr_callback(&self) -> DeferredCallback {
+        let queue = self.queue.clone();
+        let hold_off = self.hold_off.clone();
+
+        Box::new(move |state| {
+            if let Some(hold_off) = &hold_off {
+                hold_off.hold();
+            }
+            let queued = queue.send_from_isr(Event::Edge(state));
+            if let (false, Some(hold_off)) = (queued, &hold_off) {
+                hold_off.unhold();
+            }
+        })
+    }
+}
+
+impl<T: Copy + Send + 'static> Drop for DeferredTask<T> {
+    /// The handle already removed its isr, so `Stop` is the last thing the task gets,
+    /// the edges queued before it are still delivered
+    fn drop(&mut self) {
+        self.queue.send_from_task(Event::Stop, BLOCK);
+    }
+}
+
+impl<Debouncer> LeveledEdge<Debouncer, DeferredCallback>
+where
+    Debouncer: Debounce,
+{
+    /// A stack that fits a callback that logs with `println!`, a callback that doesn't can do with less
+    pub const DEFERRED_TASK_STACK: u32 = 4096;
+    /// Above the main task (1) so edges are handled promptly, below the wifi and lwip tasks (18 and up)
+    pub const DEFERRED_TASK_PRIO: u32 = 5;
+
+    /// Same as `new`, but the callback runs in a task of its own instead of the interrupt handler,
+    /// so it can block, log or touch flash. The isr only queues the edge and wakes the task.
+    /// This adds the latency of a context switch (tens of microseconds, more if `task_prio` is low)
+    /// and the task's stack to the cost of a handle. The task is created here and stops
+    /// (after delivering the edges already queued) when the handle is dropped.
+    ///
+    /// Between edges the task is blocked on its queue, so it never keeps the idle task from running
+    /// and can't trip the task watchdog however long the input stays quiet. A callback that
+    /// busy-loops can, same as any task. With the `task_wdt` feature the task is also subscribed to
+    /// the TWDT itself and fed while idle, so a callback stuck (e.g. blocked on a lock) for longer
+    /// than the TWDT timeout gets reported instead of silently swallowing edges.
+    /// task_stack: stack size of the task in bytes, at least 768 (see `DEFERRED_TASK_STACK`)
+    /// task_prio: FreeRTOS priority of the task, below 25 (see `DEFERRED_TASK_PRIO`)
+    /// Fails with `ESP_ERR_INVALID_ARG` on a stack or priority out of range and with `ESP_ERR_NO_MEM`
+    /// if there is no room for the task.
+    pub fn new_deferred(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        task_stack: u32,
+        task_prio: u32,
+        callback: impl FnMut(bool) + Send + 'static,
+    ) -> Result<Box<Self>, EspError> {
+        let task = DeferredTask::spawn(task_stack, task_prio, Box::new(callback), None)?;
+
+        LeveledEdgeBuilder::new(gpio, debouncer, task.isr_callback())
+            .deferred(task)
+            .build()
+    }
+
+    /// Same as `new_deferred`, with backpressure: the interrupt handler leaves the interrupt off after
+    /// every edge it queued, and the task enables it again once the callback returned. An input that
+    /// floods edges can't outrun the task then, it gets one edge at a time and the ones that come
+    /// while it's busy collapse into the level the pin is at when it's done (the trigger stays armed for
+    /// the level after the edge, so a change meanwhile fires as soon as the interrupt is back on).
+    ///
+    /// No path leaves the input dead: an edge that didn't fit in the queue doesn't hold the interrupt off,
+    /// and one that did is always followed by the task enabling it. `disable` and dropping the handle
+    /// win over a task that is still busy, it won't enable the interrupt behind their back.
+    /// The price is latency: an edge is only seen once the callback of the previous one returned.
+    pub fn new_deferred_backpressure(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        task_stack: u32,
+        task_prio: u32,
+        callback: impl FnMut(bool) + Send + 'static,
+    ) -> Result<Box<Self>, EspError> {
+        let hold_off = Arc::new(HoldOff::new(gpio.pin()));
+        let task = DeferredTask::spawn(task_stack, task_prio, Box::new(callback), Some(hold_off))?;
+
+        LeveledEdgeBuilder::new(gpio, debouncer, task.isr_callback())
+            .deferred(task)
+            .build()
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Selects which edges of the (debounced) input are of interest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EdgeFilter {
+    /// low to high
+    Rising,
+    /// high to low
+    Falling,
+    Both,
+}
+
+impl EdgeFilter {
+    /// Does an edge that ended in `level` pass the filter
+    #[inline(always)]
+    pub fn matches(&self, level: bool) -> bool {
+        match self {
+            EdgeFilter::Rising => level,
+            EdgeFilter::Falling => !level,
+            EdgeFilter::Both => true,
+        }
+    }
+}
+
+/// The electrical level of a pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Level {
+    Low,
+    High,
+}
+
+impl Level {
+    /// Translate a raw level (`true` = high) to active (`true`) / inactive (`false`),
+    /// where `self` is the active level
+    #[inline(always)]
+    pub fn is_active(&self, level: bool) -> bool {
+        level == (*self == Level::High)
+    }
+}
+
+impl From<bool> for Level {
+    fn from(level: bool) -> Self {
+        match level {
+            true => Level::High,
+            false => Level::Low,
+        }
+    }
+}
+
+/// What a handle does with the edges it finds it missed, see `LeveledEdgeBuilder::missed_edge_policy`
+///
+/// A missed edge is found either by `LeveledEdge::verify_and_correct`, or by the interrupt handler
+/// when the level interrupt catches up on an edge the debouncer rejected (the accepted edge goes
+/// to the same level as the previous one, see `LeveledEdgeTelemetry::missed_edge_recoveries`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissedEdgePolicy {
+    /// Deliver it like any accepted edge (output, callback and sinks), so the application
+    /// catches up on the state the pin ended at. The default, missing a state is worse
+    /// than hearing about one twice.
+    #[default]
+    FireCallback,
+    /// Only fix the tracked state, for applications that would be confused by a callback
+    /// that repeats the previous level
+    SilentResync,
+}
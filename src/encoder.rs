@@ -0,0 +1,611 @@
+use crate::debounce::{micros, Debounce};
+use crate::leveled_edge::{settle_reads, LeveledEdge};
+use crate::queue::IsrQueue;
+use crate::sink::notify_from_isr;
+use esp_idf_hal::delay::TickType;
+use esp_idf_hal::gpio::{GpioPin, Input, Pin};
+use esp_idf_hal::interrupt;
+use esp_idf_sys::{
+    gpio_get_level, tskTaskControlBlock, EspError, ESP_ERR_INVALID_ARG, ESP_ERR_NO_MEM,
+};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU32, Ordering};
+use std::sync::Arc;
+
+type EncoderCallback = Box<dyn FnMut(bool)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotaryDirection {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// The common api of the software and the hardware (PCNT) encoders,
+/// so one can be swapped for the other without touching the code that reads it
+pub trait Encoder {
+    /// The number of steps since the encoder was created, clockwise is positive
+    fn position(&self) -> i64;
+
+    /// The number of steps since the last call (or since the encoder was created)
+    fn take_delta(&mut self) -> i64;
+}
+
+/// A second pin read by the interrupt handler as soon as it runs, before settling and debouncing,
+/// so its level is the one it had at the edge (the dt pin of an encoder)
+pub(crate) struct SampledPin {
+    pin: i32,
+    level: Arc<AtomicBool>,
+}
+
+impl SampledPin {
+    #[inline(always)]
+    #[link_section = ".iram1.sampled_pin"]
+    pub(crate) fn sample(&self) {
+        let level = unsafe { gpio_get_level(self.pin) } != 0;
+        self.level.store(level, Ordering::Release);
+    }
+}
+
+/// Position and pending delta, updated by the interrupt handler
+struct Counts {
+    position: AtomicI32,
+    delta: AtomicI32,
+    /// Every step is counted the other way
+    reversed: AtomicBool,
+    /// Illegal transitions seen, see `RotaryEncoder::error_count`
+    errors: AtomicU32,
+    /// The task blocked in `RotaryEncoder::wait_change`, if any
+    waiter: AtomicPtr<tskTaskControlBlock>,
+}
+
+impl Counts {
+    fn new() -> Self {
+        Self {
+            position: AtomicI32::new(0),
+            delta: AtomicI32::new(0),
+            reversed: AtomicBool::new(false),
+            errors: AtomicU32::new(0),
+            waiter: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Count an accepted edge of `clk` to `clk_high`, with `dt_high` the level of `dt` for it.
+    /// Returns the direction and the position after the step, `None` on an illegal transition,
+    /// which is counted in `errors` and leaves the position where it was.
+    /// last_clk: The level of the previous edge, kept by the caller
+    #[inline(always)]
+    fn step(
+        &self,
+        last_clk: &mut Option<bool>,
+        clk_high: bool,
+        dt_high: bool,
+    ) -> Option<(RotaryDirection, i64)> {
+        // clk has to alternate, the same level twice means an edge of it was missed in between
+        // (clk and dt both changed within one sample), there is no telling which way it went
+        if last_clk.replace(clk_high) == Some(clk_high) {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let clockwise = (clk_high != dt_high) != self.reversed.load(Ordering::Acquire);
+        let (direction, step) = match clockwise {
+            true => (RotaryDirection::Clockwise, 1),
+            false => (RotaryDirection::CounterClockwise, -1),
+        };
+
+        let position = self.position.fetch_add(step, Ordering::AcqRel) + step;
+        self.delta.fetch_add(step, Ordering::AcqRel);
+
+        Some((direction, position as i64))
+    }
+}
+
+/// The level of `dt` a confirmed edge of `clk` is decoded with: the one sampled at the edge,
+/// or with `settled` the one `read` settles on now, after the bounce window of `clk`
+#[inline(always)]
+fn dt_for_step(settled: bool, sampled: &AtomicBool, mut read: impl FnMut() -> bool) -> bool {
+    match settled {
+        // clk was confirmed over the whole poll window, dt had the same time to stop bouncing
+        true => {
+            let first = read();
+            settle_reads(first, read)
+        }
+        false => sampled.load(Ordering::Acquire),
+    }
+}
+
+/// The decoding shared by `RotaryEncoder` and `EncoderBank`, all but where the steps are sent to
+struct Decoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    counts: Arc<Counts>,
+    _interrupt: Box<LeveledEdge<Debouncer, EncoderCallback>>,
+    _dt: GpioPin<Input>,
+}
+
+impl<Debouncer> Decoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// on_step: Called from the interrupt handler with every step
+    /// on_error: Called from the interrupt handler on an illegal transition, the position is held
+    /// settled: Read `dt` once `clk` is confirmed instead of at its edge, see `RotaryEncoder::new_settled`
+    fn new(
+        clk: GpioPin<Input>,
+        dt: GpioPin<Input>,
+        debouncer: Debouncer,
+        mut on_step: impl FnMut(Step) + 'static,
+        mut on_error: Option<Box<dyn FnMut()>>,
+        settled: bool,
+    ) -> Result<Self, EspError> {
+        // without polling the callback runs right at the edge, dt would be read mid bounce all the same
+        if settled && debouncer.poll_confirm().is_none() {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        let counts = Arc::new(Counts::new());
+
+        // reading dt in the callback would be too late on a fast spin, it's sampled at the edge
+        let dt_pin = dt.pin();
+        let dt_level = Arc::new(AtomicBool::new(false));
+        let sampled_dt = SampledPin {
+            pin: dt_pin,
+            level: dt_level.clone(),
+        };
+
+        let isr_counts = counts.clone();
+        let mut last_clk = None;
+        let mut last_step = None;
+        let on_edge: EncoderCallback = Box::new(move |state| {
+            let dt_high = dt_for_step(settled, &dt_level, || unsafe {
+                gpio_get_level(dt_pin) != 0
+            });
+
+            let (direction, position) = match isr_counts.step(&mut last_clk, state, dt_high) {
+                Some(step) => step,
+                None => {
+                    if let Some(on_error) = &mut on_error {
+                        on_error();
+                    }
+                    return;
+                }
+            };
+
+            let now = micros();
+            let interval_us = last_step.replace(now).map_or(0, |last| now - last);
+            on_step(Step {
+                direction,
+                position,
+                interval_us,
+            });
+
+            let waiter = isr_counts.waiter.load(Ordering::Acquire);
+            if !waiter.is_null() {
+                notify_from_isr(waiter, 1);
+            }
+        });
+
+        let mut builder = LeveledEdge::builder(clk, debouncer, on_edge);
+        if !settled {
+            builder = builder.sampled_pin(sampled_dt);
+        }
+        let _interrupt = builder.build()?;
+
+        Ok(Decoder {
+            counts,
+            _interrupt,
+            _dt: dt,
+        })
+    }
+
+    fn position(&self) -> i64 {
+        self.counts.position.load(Ordering::Acquire) as i64
+    }
+
+    fn take_delta(&mut self) -> i64 {
+        self.counts.delta.swap(0, Ordering::AcqRel) as i64
+    }
+
+    fn changed_since(&self, last: i64) -> Option<i64> {
+        let position = self.position();
+        (position != last).then_some(position)
+    }
+
+    /// Block the calling task on its notification until a step comes in or `timeout` passes
+    fn wait_change(&self, last: i64, timeout: TickType) -> Option<i64> {
+        let task = unsafe { esp_idf_sys::xTaskGetCurrentTaskHandle() };
+        self.counts.waiter.store(task, Ordering::Release);
+
+        // a step between reading the position and blocking still leaves the notification pending
+        let position = self.changed_since(last).or_else(|| {
+            interrupt::task::wait_notification(timeout.into());
+            self.changed_since(last)
+        });
+
+        self.counts.waiter.store(ptr::null_mut(), Ordering::Release);
+        position
+    }
+
+    fn set_reversed(&self, reversed: bool) {
+        self.counts.reversed.store(reversed, Ordering::Release);
+    }
+
+    fn error_count(&self) -> u32 {
+        self.counts.errors.load(Ordering::Relaxed)
+    }
+}
+
+/// A rotary encoder decoded in software, one step per accepted edge of `clk`
+/// Good enough for hand turned knobs, fast encoders will lose steps (see `PcntEncoder`).
+pub struct RotaryEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    decoder: Decoder<Debouncer>,
+    queue: Arc<IsrQueue<Step>>,
+    /// The position `wait_change` returned last
+    seen: i64,
+}
+
+impl<Debouncer> RotaryEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    const QUEUE_LENGTH: u32 = 100;
+
+    /// Create a new instance of `RotaryEncoder`
+    /// clk: The pin the interrupt is attached to
+    /// dt: Read on every accepted `clk` edge to tell the direction
+    pub fn new(
+        clk: GpioPin<Input>,
+        dt: GpioPin<Input>,
+        debouncer: Debouncer,
+    ) -> Result<Self, EspError> {
+        Self::create(clk, dt, debouncer, None, false)
+    }
+
+    /// Same as `new`, but the direction is decoded from the settled pair instead of the levels at the edge:
+    /// `dt` is read (until it reads the same a few times in a row) once the debouncer confirmed `clk`,
+    /// after its bounce window, rather than sampled the instant `clk` first moved.
+    /// For noisy encoders where `dt` is still bouncing at the first edge of `clk`, and reading it there
+    /// reports steps the wrong way. The step is counted a poll window later, so a fast spin loses more steps.
+    /// Only makes sense with a debouncer that confirms by polling (`PollDebounce`),
+    /// fails with `ESP_ERR_INVALID_ARG` with any other.
+    pub fn new_settled(
+        clk: GpioPin<Input>,
+        dt: GpioPin<Input>,
+        debouncer: Debouncer,
+    ) -> Result<Self, EspError> {
+        Self::create(clk, dt, debouncer, None, true)
+    }
+
+    /// Same as `new`, and `on_error` is called from the interrupt handler on every illegal transition,
+    /// see `error_count`
+    pub fn with_error_callback(
+        clk: GpioPin<Input>,
+        dt: GpioPin<Input>,
+        debouncer: Debouncer,
+        on_error: impl FnMut() + 'static,
+    ) -> Result<Self, EspError> {
+        Self::create(clk, dt, debouncer, Some(Box::new(on_error)), false)
+    }
+
+    fn create(
+        clk: GpioPin<Input>,
+        dt: GpioPin<Input>,
+        debouncer: Debouncer,
+        on_error: Option<Box<dyn FnMut()>>,
+        settled: bool,
+    ) -> Result<Self, EspError> {
+        let queue = Arc::new(
+            IsrQueue::new(Self::QUEUE_LENGTH)
+                .ok_or_else(|| EspError::from(ESP_ERR_NO_MEM).unwrap())?,
+        );
+
+        let isr_queue = queue.clone();
+        let on_step = move |step| {
+            isr_queue.send_from_isr(step);
+        };
+        let decoder = Decoder::new(clk, dt, debouncer, on_step, on_error, settled)?;
+
+        Ok(RotaryEncoder {
+            decoder,
+            queue,
+            seen: 0,
+        })
+    }
+
+    /// The illegal transitions seen so far: `clk` accepted at the same level twice in a row,
+    /// meaning an edge of it was missed (both signals changed between two samples).
+    /// The direction of such a step can't be known, so it is not counted instead of guessed
+    /// and the position stays where it was.
+    pub fn error_count(&self) -> u32 {
+        self.decoder.error_count()
+    }
+
+    /// Count the steps the other way, for encoders that are wired or mounted so they turn backwards,
+    /// instead of swapping `clk` and `dt`. Applies to the directions, `position` and `take_delta` alike.
+    /// Meant to be set right after `new`, steps counted before it keep their sign.
+    pub fn reversed(self, reversed: bool) -> Self {
+        self.decoder.set_reversed(reversed);
+        self
+    }
+
+    /// The position if it is not `last` anymore, so a display can skip redrawing when nothing moved
+    /// Only compared for equality, so a position that went back and forth to `last` in between
+    /// is no change, and one that wrapped around is still a change.
+    pub fn changed_since(&self, last: i64) -> Option<i64> {
+        self.decoder.changed_since(last)
+    }
+
+    /// Wait up to `timeout` for the position to differ from the one this returned last (0 at first),
+    /// and return it. Steps while the caller was busy coalesce into one change, none is lost between calls.
+    /// Blocks on the calling task's notification, woken by the interrupt handler on every step,
+    /// so that task shouldn't also wait on notifications of its own (e.g. `LeveledEdge::notify_task`).
+    /// Returns `None` on timeout, or if the steps since went back to the same position.
+    pub fn wait_change(&mut self, timeout: TickType) -> Option<i64> {
+        let position = self.decoder.wait_change(self.seen, timeout)?;
+        self.seen = position;
+
+        Some(position)
+    }
+
+    /// Wait up to `timeout` for the next step, must be called from a task
+    pub fn wait_on_direction(&mut self, timeout: TickType) -> Option<RotaryDirection> {
+        self.next_event(timeout).map(|event| event.direction)
+    }
+
+    /// Wait up to `timeout` for the next step with its direction, position and velocity,
+    /// must be called from a task. Reads the same queue as `wait_on_direction`, so a step is
+    /// returned by one or the other, not both.
+    pub fn next_event(&mut self, timeout: TickType) -> Option<EncoderEvent> {
+        self.queue.recv(timeout).map(Step::event)
+    }
+}
+
+impl<Debouncer> Encoder for RotaryEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    fn position(&self) -> i64 {
+        self.decoder.position()
+    }
+
+    fn take_delta(&mut self) -> i64 {
+        self.decoder.take_delta()
+    }
+}
+
+/// A step of an encoder, relative and absolute at once, so a menu can move by `direction`
+/// while a volume knob jumps to `position`, reading the same events.
+/// All the fields are of the same step, taken by the interrupt handler when it was counted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EncoderEvent {
+    /// Which way this one step went
+    pub direction: RotaryDirection,
+    /// The position of the encoder right after the step, see `Encoder::position`
+    pub position: i64,
+    /// Steps per second from the time since the previous step, signed like `position`
+    /// (clockwise is positive), e.g. to scroll faster on a fast spin.
+    /// 0.0 on the first step, there is nothing to measure it from.
+    pub velocity: f32,
+}
+
+/// What the interrupt handler queues for an `EncoderEvent`, the velocity is only worked out
+/// by the task receiving it, the FPU is off limits in interrupt handlers on the xtensa chips
+#[derive(Clone, Copy)]
+struct Step {
+    direction: RotaryDirection,
+    position: i64,
+    /// Microseconds since the previous step, 0 if there was none
+    interval_us: i64,
+}
+
+impl Step {
+    fn event(self) -> EncoderEvent {
+        let speed = match self.interval_us {
+            0 => 0.0,
+            interval_us => 1_000_000.0 / interval_us as f32,
+        };
+
+        EncoderEvent {
+            direction: self.direction,
+            position: self.position,
+            velocity: match self.direction {
+                RotaryDirection::Clockwise => speed,
+                RotaryDirection::CounterClockwise => -speed,
+            },
+        }
+    }
+}
+
+/// Several software encoders feeding a single queue, so one task can serve a whole panel of knobs
+/// instead of waiting on a queue per encoder (or needing a task per encoder).
+/// The encoders are told apart by their index in the order they were given to `new`.
+pub struct EncoderBank<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    decoders: Vec<Decoder<Debouncer>>,
+    queue: Arc<IsrQueue<(usize, Step)>>,
+}
+
+impl<Debouncer> EncoderBank<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// Shared by all the encoders, a busy panel may want more than a single `RotaryEncoder`
+    const QUEUE_LENGTH: u32 = 200;
+
+    /// Create a new instance of `EncoderBank`
+    /// encoders: The `clk` and `dt` pins and the debouncer of each encoder, as in `RotaryEncoder::new`
+    pub fn new(
+        encoders: impl IntoIterator<Item = (GpioPin<Input>, GpioPin<Input>, Debouncer)>,
+    ) -> Result<Self, EspError> {
+        Self::create(encoders, false)
+    }
+
+    /// Same as `new`, with every encoder decoded from the settled pair, see `RotaryEncoder::new_settled`
+    pub fn new_settled(
+        encoders: impl IntoIterator<Item = (GpioPin<Input>, GpioPin<Input>, Debouncer)>,
+    ) -> Result<Self, EspError> {
+        Self::create(encoders, true)
+    }
+
+    fn create(
+        encoders: impl IntoIterator<Item = (GpioPin<Input>, GpioPin<Input>, Debouncer)>,
+        settled: bool,
+    ) -> Result<Self, EspError> {
+        let queue = Arc::new(
+            IsrQueue::new(Self::QUEUE_LENGTH)
+                .ok_or_else(|| EspError::from(ESP_ERR_NO_MEM).unwrap())?,
+        );
+
+        let decoders = encoders
+            .into_iter()
+            .enumerate()
+            .map(|(index, (clk, dt, debouncer))| {
+                let isr_queue = queue.clone();
+                let on_step = move |step| {
+                    isr_queue.send_from_isr((index, step));
+                };
+                Decoder::new(clk, dt, debouncer, on_step, None, settled)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(EncoderBank { decoders, queue })
+    }
+
+    /// Wait up to `timeout` for the next step of any of the encoders, must be called from a task
+    /// Returns the index of the encoder that moved and the step.
+    pub fn next_event(&mut self, timeout: TickType) -> Option<(usize, EncoderEvent)> {
+        self.queue
+            .recv(timeout)
+            .map(|(index, step)| (index, step.event()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.decoders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decoders.is_empty()
+    }
+
+    /// The number of steps of encoder `index` since it was created, see `Encoder::position`
+    /// Panics if `index` is out of range.
+    pub fn position(&self, index: usize) -> i64 {
+        self.decoders[index].position()
+    }
+
+    /// The number of steps of encoder `index` since the last call, see `Encoder::take_delta`
+    /// Panics if `index` is out of range.
+    pub fn take_delta(&mut self, index: usize) -> i64 {
+        self.decoders[index].take_delta()
+    }
+
+    /// The illegal transitions of encoder `index`, see `RotaryEncoder::error_count`
+    /// Panics if `index` is out of range.
+    pub fn error_count(&self, index: usize) -> u32 {
+        self.decoders[index].error_count()
+    }
+
+    /// Count the steps of encoder `index` the other way, see `RotaryEncoder::reversed`
+    /// Panics if `index` is out of range.
+    pub fn set_reversed(&mut self, index: usize, reversed: bool) {
+        self.decoders[index].set_reversed(reversed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_follow_dt() {
+        let counts = Counts::new();
+        let mut last_clk = None;
+
+        assert_eq!(
+            counts.step(&mut last_clk, true, false),
+            Some((RotaryDirection::Clockwise, 1))
+        );
+        assert_eq!(
+            counts.step(&mut last_clk, false, true),
+            Some((RotaryDirection::Clockwise, 2))
+        );
+        assert_eq!(
+            counts.step(&mut last_clk, true, true),
+            Some((RotaryDirection::CounterClockwise, 1))
+        );
+        assert_eq!(counts.errors.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn illegal_transition_holds_the_position() {
+        let counts = Counts::new();
+        let mut last_clk = None;
+
+        counts.step(&mut last_clk, true, false);
+        // clk accepted high twice in a row, an edge of it was missed
+        assert_eq!(counts.step(&mut last_clk, true, false), None);
+
+        assert_eq!(counts.position.load(Ordering::Relaxed), 1);
+        assert_eq!(counts.delta.load(Ordering::Relaxed), 1);
+        assert_eq!(counts.errors.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn fast_spin_decodes_the_dt_of_the_edge() {
+        let counts = Counts::new();
+        let mut last_clk = None;
+        let sampled = AtomicBool::new(false);
+
+        // a fast spin, 8 steps clockwise and 8 back: dt is sampled at the edge of clk,
+        // by the time the callback runs it already moved on to its next level
+        let mut clk = false;
+        for (steps, clockwise) in [(8, true), (8, false)] {
+            for _ in 0..steps {
+                clk = !clk;
+                let at_edge = clk != clockwise;
+                sampled.store(at_edge, Ordering::Release);
+                let mut late = || !at_edge;
+
+                let (direction, _) = counts
+                    .step(&mut last_clk, clk, dt_for_step(false, &sampled, &mut late))
+                    .unwrap();
+                let expected = match clockwise {
+                    true => RotaryDirection::Clockwise,
+                    false => RotaryDirection::CounterClockwise,
+                };
+                assert_eq!(direction, expected);
+            }
+
+            let position = counts.position.load(Ordering::Relaxed);
+            assert_eq!(position, if clockwise { 8 } else { 0 });
+        }
+        assert_eq!(counts.errors.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn settled_dt_gets_the_direction_right() {
+        // a clockwise step, clk went high with dt low, but dt was still bouncing
+        // when clk first moved and got sampled high
+        let sampled = AtomicBool::new(true);
+        let mut reads = [true, false, false, false, false].into_iter();
+        let mut read = move || reads.next().unwrap_or(false);
+
+        let at_edge = dt_for_step(false, &sampled, &mut read);
+        assert_eq!(
+            Counts::new().step(&mut None, true, at_edge),
+            Some((RotaryDirection::CounterClockwise, -1))
+        );
+
+        let settled = dt_for_step(true, &sampled, &mut read);
+        assert_eq!(
+            Counts::new().step(&mut None, true, settled),
+            Some((RotaryDirection::Clockwise, 1))
+        );
+    }
+}
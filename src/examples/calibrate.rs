@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use esp_idf_hal::prelude::Peripherals;
+use esp_idf_sys as _; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
+
+use esp_leveled_edge::{ClassicDebounce, LeveledEdge};
+
+/// Takes the reading of a sensor, made up for the example
+fn read_sensor() -> u32 {
+    42
+}
+
+fn main() {
+    esp_idf_sys::link_patches();
+    println!("Calibration example!");
+
+    let pins = Peripherals::take().unwrap().pins;
+    // active low button with a pull up: low while pressed
+    let button = LeveledEdge::state_only(
+        pins.gpio4.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+    )
+    .unwrap();
+
+    println!("release the button to start");
+    button
+        .wait_for_level(true, Duration::from_secs(60 * 60))
+        .unwrap();
+
+    for step in ["zero", "full scale"] {
+        println!("set the sensor to {step} and press the button");
+        if !button
+            .wait_for_level(false, Duration::from_secs(30))
+            .unwrap()
+        {
+            println!("no press, calibration aborted");
+            return;
+        }
+        println!("{step}: {}", read_sensor());
+
+        // don't take the next step on the same press
+        if !button.wait_for_level(true, Duration::from_secs(2)).unwrap() {
+            println!("button held too long, calibration aborted");
+            return;
+        }
+    }
+
+    println!("calibration done");
+}
@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use esp_idf_hal::prelude::Peripherals;
+use esp_idf_sys::{self as _, vTaskDelay}; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
+
+use esp_leveled_edge::{ClassicDebounce, EdgeFilter, LeveledEdge};
+
+/// Bumped by the interrupt handler on every press
+static PRESSES: AtomicU32 = AtomicU32::new(0);
+
+fn main() {
+    esp_idf_sys::link_patches();
+    println!("Count presses example!");
+
+    let pins = Peripherals::take().unwrap().pins;
+    // a button with a pull up, pressing it is a falling edge
+    let _button = LeveledEdge::count_into(
+        pins.gpio4.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+        &PRESSES,
+        EdgeFilter::Falling,
+    )
+    .unwrap();
+
+    loop {
+        // once a second, print how many presses came in since the last time
+        unsafe { vTaskDelay(100) };
+        let presses = PRESSES.swap(0, Ordering::Relaxed);
+        if presses > 0 {
+            println!("{presses} presses");
+        }
+    }
+}
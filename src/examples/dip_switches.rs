@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use esp_idf_sys::{self as _, vTaskDelay}; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
+
+use esp_leveled_edge::{ClassicDebounce, Level, LeveledEdge, PinConfig, PullMode};
+
+/// The gpios of the switches, bit 0 first
+const SWITCH_PINS: [i32; 8] = [4, 5, 13, 14, 16, 17, 18, 19];
+
+fn main() {
+    esp_idf_sys::link_patches();
+    println!("DIP switches example!");
+
+    // every switch pulls its pin to ground when on
+    let config = PinConfig {
+        pull: PullMode::Up,
+        active: Level::Low,
+    };
+    let configs = SWITCH_PINS.map(|pin| (pin, config));
+
+    let byte = Arc::new(AtomicU8::new(0));
+    let isr_byte = byte.clone();
+    let on_switch = move |index: u32, on: bool| {
+        let bit = 1 << index;
+        match on {
+            true => isr_byte.fetch_or(bit, Ordering::Relaxed),
+            false => isr_byte.fetch_and(!bit, Ordering::Relaxed),
+        };
+    };
+
+    // the pins are not taken from `Peripherals` anywhere in this example
+    let switches = unsafe {
+        LeveledEdge::new_bank(
+            &configs,
+            || ClassicDebounce::new(Duration::from_millis(20)),
+            on_switch,
+        )
+    }
+    .unwrap();
+
+    // the callbacks only report changes, start from where the switches are
+    for (index, switch) in switches.iter().enumerate() {
+        if switch.is_active() {
+            byte.fetch_or(1 << index, Ordering::Relaxed);
+        }
+    }
+
+    let mut last = None;
+    loop {
+        let value = byte.load(Ordering::Relaxed);
+        if last != Some(value) {
+            println!("switches: {value:08b} ({value})");
+            last = Some(value);
+        }
+
+        unsafe { vTaskDelay(10) };
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use esp_idf_hal::prelude::Peripherals;
+use esp_idf_sys as _; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
+
+use esp_leveled_edge::{AsKey, ClassicDebounce, IsrQueue, KeyEvent, Level, LeveledEdge};
+
+// HID usage ids of the keys
+const KEY_A: u8 = 0x04;
+const KEY_B: u8 = 0x05;
+const KEY_ENTER: u8 = 0x28;
+
+fn main() {
+    esp_idf_sys::link_patches();
+    println!("Keys example!");
+
+    let pins = Peripherals::take().unwrap().pins;
+    let events = Arc::new(IsrQueue::<KeyEvent<u8>>::new(32).unwrap());
+
+    // two buttons that pull the pin high when pressed
+    let isr_events = events.clone();
+    let _a = LeveledEdge::as_key(
+        pins.gpio4.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+        KEY_A,
+        move |event| {
+            isr_events.send_from_isr(event);
+        },
+    )
+    .unwrap();
+
+    let isr_events = events.clone();
+    let _b = LeveledEdge::as_key(
+        pins.gpio5.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+        KEY_B,
+        move |event| {
+            isr_events.send_from_isr(event);
+        },
+    )
+    .unwrap();
+
+    // and one with a pull up, pressed is low
+    let isr_events = events.clone();
+    let callback = AsKey {
+        key: KEY_ENTER,
+        callback: move |event| {
+            isr_events.send_from_isr(event);
+        },
+    };
+    let _enter = LeveledEdge::builder(
+        pins.gpio18.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+        callback,
+    )
+    .active(Level::Low)
+    .build()
+    .unwrap();
+
+    // the keys held down, what a HID keyboard report would carry
+    let mut held: Vec<u8> = Vec::new();
+    loop {
+        if let Some(event) = events.recv(Duration::from_millis(1000).into()) {
+            match event {
+                KeyEvent::Press(key) => held.push(key),
+                KeyEvent::Release(key) => held.retain(|&k| k != key),
+            }
+            println!("{:?}, held {:02x?}", event, held);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use esp_idf_hal::prelude::Peripherals;
+use esp_idf_sys::{self as _, vTaskDelay}; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
+
+use esp_leveled_edge::{
+    ClassicDebounce, FilterDebounce, InputEvent, InputSource, Level, LeveledEdge, RotaryDirection,
+    RotaryEncoder,
+};
+
+const ITEMS: [&str; 3] = ["Brightness", "Volume", "Exit"];
+
+/// A menu that doesn't know where its input comes from
+enum Menu {
+    Browsing(usize),
+    Editing(usize, i32),
+}
+
+impl Menu {
+    fn handle(self, event: InputEvent) -> Self {
+        match (self, event) {
+            (Menu::Browsing(item), InputEvent::Rotate { direction, .. }) => {
+                let item = match direction {
+                    RotaryDirection::Clockwise => (item + 1) % ITEMS.len(),
+                    RotaryDirection::CounterClockwise => (item + ITEMS.len() - 1) % ITEMS.len(),
+                };
+                println!("> {}", ITEMS[item]);
+                Menu::Browsing(item)
+            }
+            (Menu::Browsing(item), InputEvent::Button { pressed: true, .. }) => {
+                println!("editing {}", ITEMS[item]);
+                Menu::Editing(item, 0)
+            }
+            (Menu::Editing(item, value), InputEvent::Rotate { direction, .. }) => {
+                let value = match direction {
+                    RotaryDirection::Clockwise => value + 1,
+                    RotaryDirection::CounterClockwise => value - 1,
+                };
+                println!("{} = {}", ITEMS[item], value);
+                Menu::Editing(item, value)
+            }
+            (Menu::Editing(item, _), InputEvent::Button { pressed: true, .. }) => {
+                println!("> {}", ITEMS[item]);
+                Menu::Browsing(item)
+            }
+            (menu, _) => menu,
+        }
+    }
+}
+
+fn main() {
+    esp_idf_sys::link_patches();
+    println!("Menu example!");
+
+    let pins = Peripherals::take().unwrap().pins;
+    let knob = RotaryEncoder::new(
+        pins.gpio25.into_input().unwrap().degrade(),
+        pins.gpio27.into_input().unwrap().degrade(),
+        FilterDebounce::new(Duration::from_millis(20)),
+    )
+    .unwrap();
+    let select = LeveledEdge::builder(
+        pins.gpio26.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+        |_| {},
+    )
+    .active(Level::Low)
+    .build()
+    .unwrap();
+
+    let mut sources: [Box<dyn InputSource>; 2] = [Box::new(knob), select];
+    let mut menu = Menu::Browsing(0);
+
+    loop {
+        for source in sources.iter_mut() {
+            while let Some(event) = source.next() {
+                menu = menu.handle(event);
+            }
+        }
+
+        unsafe { vTaskDelay(1) };
+    }
+}
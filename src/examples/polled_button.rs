@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use esp_idf_hal::prelude::Peripherals;
+use esp_idf_sys::{self as _, vTaskDelay}; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
+
+use esp_leveled_edge::{ClassicDebounce, PolledEdge};
+
+fn main() {
+    esp_idf_sys::link_patches();
+    println!("Polled button example!");
+
+    let pins = Peripherals::take().unwrap().pins;
+    let mut button = PolledEdge::new(
+        pins.gpio4.into_input().unwrap().degrade(),
+        ClassicDebounce::new(Duration::from_millis(20)),
+    )
+    .unwrap();
+
+    // no interrupts, check the button once per tick and handle everything that changed since
+    loop {
+        for state in button.poll_iter() {
+            println!("button {}", if state { "released" } else { "pressed" });
+        }
+
+        unsafe { vTaskDelay(1) };
+    }
+}
@@ -0,0 +1,56 @@
+use crate::telemetry::Counters;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The flags and counters of a handle, lock free, see `LeveledEdge::flags`
+///
+/// Every method is a single atomic load or store with `Relaxed` ordering, no critical section and no
+/// gpio call, so it can be called from a hot loop on any task or core while the handle's isr runs.
+/// `Relaxed` is enough since none of the flags guards other data: the handler sees a `mute` on one of
+/// its next runs (an edge it is delivering at that moment still goes out), and the counters are
+/// each exact on their own but not a consistent snapshot of all of them together.
+///
+/// Only what is an atomic flag in the handler is here. Enabling and disabling the interrupt write
+/// the gpio registers under esp-idf's spinlock, and swapping the callback, the debouncer or the pull
+/// needs the interrupt off meanwhile, those stay on `LeveledEdge` behind `&mut`.
+#[derive(Clone, Copy)]
+pub struct EdgeFlags<'a> {
+    pub(crate) muted: &'a AtomicBool,
+    pub(crate) enabled: &'a AtomicBool,
+    pub(crate) pin_state: &'a AtomicBool,
+    pub(crate) counters: &'a Counters,
+}
+
+impl EdgeFlags<'_> {
+    /// See `LeveledEdge::mute`
+    pub fn mute(&self) {
+        self.muted.store(true, Ordering::Relaxed);
+    }
+
+    pub fn unmute(&self) {
+        self.muted.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// See `LeveledEdge::is_enabled`
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// See `LeveledEdge::current_state`
+    pub fn current_state(&self) -> bool {
+        self.pin_state.load(Ordering::Relaxed)
+    }
+
+    /// See `LeveledEdgeTelemetry::accepted`
+    pub fn accepted(&self) -> u32 {
+        Counters::load(&self.counters.accepted)
+    }
+
+    /// See `LeveledEdgeTelemetry::dropped`
+    pub fn dropped(&self) -> u32 {
+        Counters::load(&self.counters.dropped)
+    }
+}
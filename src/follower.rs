@@ -0,0 +1,21 @@
+use esp_idf_hal::gpio::{GpioPin, Output, Pin};
+use esp_idf_sys::gpio_set_level;
+
+/// An output pin driven straight from the interrupt handler to mirror the input
+pub(crate) struct Follower {
+    output: GpioPin<Output>,
+    invert: bool,
+}
+
+impl Follower {
+    pub(crate) fn new(output: GpioPin<Output>, invert: bool) -> Self {
+        Self { output, invert }
+    }
+
+    /// Uses `gpio_set_level` directly, which lives in IRAM and is safe to call from the isr
+    #[inline(always)]
+    #[link_section = ".iram1.follower_set"]
+    pub(crate) fn set(&self, level: bool) {
+        unsafe { gpio_set_level(self.output.pin(), (level != self.invert) as u32) };
+    }
+}
@@ -0,0 +1,83 @@
+use crate::debounce::Debounce;
+use crate::leveled_edge::LeveledEdge;
+use crate::timer::EspTimer;
+use esp_idf_hal::gpio::{GpioPin, Input};
+use esp_idf_sys::EspError;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+type CounterCallback = Box<dyn FnMut(bool)>;
+
+struct Counts {
+    /// Rising edges in the current window, saturates instead of wrapping
+    edges: AtomicU32,
+    /// The result of the last window, the bits of an `f32`
+    hz: AtomicU32,
+}
+
+/// Counts rising edges over a window and reports them as a frequency (tachometers, flow sensors...)
+///
+/// A periodic `esp_timer` closes the window every `window` and starts the next one, so the reading
+/// is the average over the last whole window and lags by up to one window.
+/// Every edge is an interrupt, which puts the practical limit at a few tens of kHz
+/// (less with other busy interrupts around), well before the counter itself could overflow.
+pub struct FrequencyCounter<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    counts: Arc<Counts>,
+    _timer: EspTimer,
+    _interrupt: Box<LeveledEdge<Debouncer, CounterCallback>>,
+}
+
+impl<Debouncer> FrequencyCounter<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// window: How long each count runs for, longer is steadier and slower to follow changes
+    pub fn new(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        window: Duration,
+    ) -> Result<Self, EspError> {
+        let counts = Arc::new(Counts {
+            edges: AtomicU32::new(0),
+            hz: AtomicU32::new(0f32.to_bits()),
+        });
+
+        let isr_counts = counts.clone();
+        let on_edge: CounterCallback = Box::new(move |level| {
+            if level {
+                let _ =
+                    isr_counts
+                        .edges
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |edges| {
+                            edges.checked_add(1)
+                        });
+            }
+        });
+
+        let timer_counts = counts.clone();
+        let window_secs = window.as_secs_f32();
+        let timer = EspTimer::new(move || {
+            let edges = timer_counts.edges.swap(0, Ordering::AcqRel);
+            let hz = edges as f32 / window_secs;
+            timer_counts.hz.store(hz.to_bits(), Ordering::Release);
+        })?;
+
+        let _interrupt = LeveledEdge::new(gpio, debouncer, on_edge)?;
+        timer.start_periodic(window)?;
+
+        Ok(FrequencyCounter {
+            counts,
+            _timer: timer,
+            _interrupt,
+        })
+    }
+
+    /// The frequency measured over the last whole window, 0 until the first window closed
+    pub fn frequency_hz(&self) -> f32 {
+        f32::from_bits(self.counts.hz.load(Ordering::Acquire))
+    }
+}
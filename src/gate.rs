@@ -0,0 +1,85 @@
+use crate::debounce::window_micros;
+use crate::edge::EdgeFilter;
+use std::time::Duration;
+
+/// A step between the debouncer and the callback that decides whether an accepted edge is delivered
+///
+/// `allow` runs in the interrupt handler: it must not block, allocate or log, and anything it calls
+/// must be safe to call from an isr (in IRAM if the isr is). Keep it to a few comparisons.
+/// level: the level the edge went to, relative to the active level like the callback's argument
+/// now_us: the time of the edge, as `esp_timer_get_time`
+pub trait Gate {
+    fn allow(&mut self, level: bool, now_us: i64) -> bool;
+}
+
+impl Gate for EdgeFilter {
+    #[inline(always)]
+    fn allow(&mut self, level: bool, _now_us: i64) -> bool {
+        self.matches(level)
+    }
+}
+
+/// Deliver at most one edge every `min_interval`, the ones in between are dropped
+pub struct RateLimit {
+    min_interval: i64,
+    last: Option<i64>,
+}
+
+impl RateLimit {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval: window_micros(min_interval),
+            last: None,
+        }
+    }
+}
+
+impl Gate for RateLimit {
+    #[inline(always)]
+    fn allow(&mut self, _level: bool, now_us: i64) -> bool {
+        if let Some(last) = self.last {
+            if now_us - last < self.min_interval {
+                return false;
+            }
+        }
+
+        self.last = Some(now_us);
+        true
+    }
+}
+
+/// Deliver only every `n`th edge, starting with the `n`th
+pub struct EveryNth {
+    n: u32,
+    count: u32,
+}
+
+impl EveryNth {
+    pub fn new(n: u32) -> Self {
+        assert!(n > 0, "EveryNth needs n > 0");
+        Self { n, count: 0 }
+    }
+}
+
+impl Gate for EveryNth {
+    #[inline(always)]
+    fn allow(&mut self, _level: bool, _now_us: i64) -> bool {
+        self.count += 1;
+        if self.count < self.n {
+            return false;
+        }
+
+        self.count = 0;
+        true
+    }
+}
+
+/// Deliver exactly the edges the inner gate would drop, e.g. `Invert(EdgeFilter::Rising)`
+pub struct Invert<G: Gate>(pub G);
+
+impl<G: Gate> Gate for Invert<G> {
+    #[inline(always)]
+    fn allow(&mut self, level: bool, now_us: i64) -> bool {
+        !self.0.allow(level, now_us)
+    }
+}
@@ -0,0 +1,676 @@
+use crate::debounce::{micros, window_micros, Debounce};
+use crate::leveled_edge::LeveledEdge;
+use crate::timer::EspTimer;
+use esp_idf_hal::gpio::{GpioPin, Input};
+use esp_idf_sys::{EspError, ESP_ERR_INVALID_ARG, ESP_ERR_NO_MEM};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+type GroupCallback = Box<dyn FnMut(bool)>;
+
+/// A set of pins that have to be pressed together, see `LeveledEdgeGroup::on_chord`
+struct ChordDetector {
+    mask: u64,
+    window: i64,
+    /// Fired already, waiting for one of the keys to be released before it can fire again
+    fired: bool,
+    /// Completed by the edge being handled, its callback is called once the lock is released
+    pending: bool,
+    callback: Box<dyn FnMut()>,
+}
+
+/// Hands the group state to the callback a window after the first edge of a burst, see `LeveledEdgeGroup::on_batch`
+struct Batch {
+    timer: EspTimer,
+    window_us: u64,
+}
+
+/// What the chord detection and the group callbacks need, only touched in a critical section
+/// by the interrupt handlers of the group and the task changing it alike (see `GroupState::with_shared`),
+/// except for the callbacks, which a handler calls with the lock released (see `GroupState::dispatch`)
+struct IsrShared {
+    pressed_at: [i64; 64],
+    detectors: Vec<ChordDetector>,
+    on_change: Option<Box<dyn FnMut(u64)>>,
+    batch: Option<Batch>,
+    /// A handler is calling the callbacks, nothing may be replaced or freed until it's done
+    dispatching: bool,
+}
+
+/// The debounced state of every pin in the group, one bit per pin
+/// Kept in two 32 bit atomics since that's the widest atomic on esp32
+struct GroupState {
+    bits: [AtomicU32; 2],
+    shared: UnsafeCell<IsrShared>,
+}
+
+impl GroupState {
+    fn new() -> Self {
+        Self {
+            bits: [AtomicU32::new(0), AtomicU32::new(0)],
+            shared: UnsafeCell::new(IsrShared {
+                pressed_at: [0; 64],
+                detectors: Vec::new(),
+                on_change: None,
+                batch: None,
+                dispatching: false,
+            }),
+        }
+    }
+
+    /// Run `f` on what the handlers share, from the task changing it: in a critical section,
+    /// which the handlers take as well, once no handler is calling the callbacks (on the other core)
+    fn with_shared<R>(&self, f: impl FnOnce(&mut IsrShared) -> R) -> R {
+        let mut f = Some(f);
+
+        loop {
+            let result = critical_section::with(|_| {
+                let shared = unsafe { &mut *self.shared.get() };
+                match shared.dispatching {
+                    true => None,
+                    false => f.take().map(|f| f(shared)),
+                }
+            });
+
+            if let Some(result) = result {
+                return result;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    #[inline(always)]
+    fn set(&self, index: usize, state: bool) {
+        let word = &self.bits[index / 32];
+        let mask = 1 << (index % 32);
+
+        match state {
+            true => word.fetch_or(mask, Ordering::AcqRel),
+            false => word.fetch_and(!mask, Ordering::AcqRel),
+        };
+    }
+
+    fn snapshot(&self) -> u64 {
+        Self::load(&self.bits)
+    }
+
+    fn load(bits: &[AtomicU32; 2]) -> u64 {
+        let low = bits[0].load(Ordering::Acquire) as u64;
+        let high = bits[1].load(Ordering::Acquire) as u64;
+
+        high << 32 | low
+    }
+
+    /// Called from the handler of pin `index` after its bit was updated: the chords and the batch
+    /// are updated under the lock, the callbacks are called once it is released, so user code
+    /// never runs with it held. The task side waits for `dispatching` to clear meanwhile.
+    #[inline(always)]
+    fn dispatch(&self, index: usize, active: bool) {
+        let now = micros();
+        let held = self.snapshot();
+
+        critical_section::with(|_| {
+            let shared = unsafe { &mut *self.shared.get() };
+            shared.dispatching = true;
+            Self::detect_chords(shared, index, active, held, now);
+
+            // the first edge of a burst opens the window, the ones after it fall into the same batch
+            if let Some(batch) = &shared.batch {
+                batch.timer.start_once_from_isr(batch.window_us);
+            }
+        });
+
+        // the handlers of the group run one at a time, and the task waits, this one has it to itself
+        let shared = unsafe { &mut *self.shared.get() };
+        for chord in shared.detectors.iter_mut().filter(|chord| chord.pending) {
+            chord.pending = false;
+            (chord.callback)();
+        }
+        if let Some(on_change) = &mut shared.on_change {
+            on_change(held);
+        }
+
+        critical_section::with(|_| shared.dispatching = false);
+    }
+
+    /// Catch up the pins of a guard being dropped, each on its own, while they are all still held off,
+    /// so a correction firing the group callbacks races no handler. The bit of each follows its level.
+    /// Disabled pins are left as they are, `enable` reads them again anyway.
+    fn catch_up<'a, P: CatchUp + 'a>(&self, pins: impl Iterator<Item = (usize, &'a mut P)>) {
+        for (index, pin) in pins.filter(|(_, pin)| pin.is_enabled()) {
+            let level = pin.catch_up();
+            self.set(index, level);
+        }
+    }
+
+    /// Mark the chords the edge of pin `index` completed as `pending`, `held` is the group state after it
+    #[inline(always)]
+    fn detect_chords(shared: &mut IsrShared, index: usize, active: bool, held: u64, now: i64) {
+        let bit = 1 << index;
+
+        if active {
+            shared.pressed_at[index] = now;
+        }
+
+        for chord in shared
+            .detectors
+            .iter_mut()
+            .filter(|chord| chord.mask & bit != 0)
+        {
+            if !active {
+                chord.fired = false;
+                continue;
+            }
+
+            if chord.fired || held & chord.mask != chord.mask {
+                continue;
+            }
+
+            // every key of the chord is held, the first one pressed must be recent enough
+            let first = (0..64)
+                .filter(|i| chord.mask & (1 << i) != 0)
+                .map(|i| shared.pressed_at[i])
+                .min()
+                .unwrap_or(now);
+
+            if now - first <= chord.window {
+                chord.fired = true;
+                chord.pending = true;
+            }
+        }
+    }
+}
+
+/// What a group guard needs of a pin to catch it up, see `GroupState::catch_up`
+trait CatchUp {
+    fn is_enabled(&self) -> bool;
+    /// Deliver the edge it missed while held off, if it did, returns the level it is tracked at after
+    fn catch_up(&mut self) -> bool;
+}
+
+impl<Debouncer> CatchUp for LeveledEdge<Debouncer, GroupCallback>
+where
+    Debouncer: Debounce,
+{
+    fn is_enabled(&self) -> bool {
+        LeveledEdge::is_enabled(self)
+    }
+
+    fn catch_up(&mut self) -> bool {
+        // only fails for a bad pin, it stays armed for the level it was tracked at then
+        let _ = self.correct_missed_edge();
+        self.current_state()
+    }
+}
+
+/// A pin of a `LeveledEdgeGroup`
+pub struct PinSlot<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// `None` once the pin was removed, the index is free for the next `add`
+    handle: Option<Box<LeveledEdge<Debouncer, GroupCallback>>>,
+}
+
+/// A group of up to 64 leveled edge pins (a keypad, a bank of switches...)
+/// that keeps the debounced state of all of them in a single bitmask
+///
+/// `Pins` is where the pins are kept: a `Vec` by default, so pins can be added (and removed) one at a time
+/// with `add`, or a fixed array for a group whose pins are all known upfront, see `FixedLeveledEdgeGroup`.
+pub struct LeveledEdgeGroup<Debouncer, Pins = Vec<PinSlot<Debouncer>>>
+where
+    Debouncer: Debounce,
+{
+    pins: Pins,
+    state: Rc<GroupState>,
+    /// The snapshot as far as `InputSource::next` reported it
+    pub(crate) reported: u64,
+    _debouncer: PhantomData<Debouncer>,
+}
+
+/// A `LeveledEdgeGroup` of exactly `N` pins kept in a fixed array, created with `from_pins`
+/// The number of pins is checked at compile time (64 at most) and the group never grows,
+/// so its size is known upfront. Each pin's handle is still boxed, its address has to stay put for its isr.
+pub type FixedLeveledEdgeGroup<Debouncer, const N: usize> =
+    LeveledEdgeGroup<Debouncer, [PinSlot<Debouncer>; N]>;
+
+impl<Debouncer, Pins> LeveledEdgeGroup<Debouncer, Pins>
+where
+    Debouncer: Debounce,
+{
+    pub const MAX_PINS: usize = 64;
+
+    fn with_pins(pins: Pins) -> Self {
+        Self {
+            pins,
+            state: Rc::new(GroupState::new()),
+            reported: 0,
+            _debouncer: PhantomData,
+        }
+    }
+
+    /// Create the handle of the pin at `index` of the group and load its initial state
+    fn attach(
+        &mut self,
+        index: usize,
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        mut callback: impl FnMut(bool) + 'static,
+    ) -> Result<PinSlot<Debouncer>, EspError> {
+        let state = self.state.clone();
+        let on_edge: GroupCallback = Box::new(move |level| {
+            state.set(index, level);
+            state.dispatch(index, level);
+            callback(level);
+        });
+
+        let handle = LeveledEdge::new(gpio, debouncer, on_edge)?;
+
+        // the handle is already armed, make sure its isr doesn't race the initial state
+        handle.isr_off();
+        self.state.set(index, handle.current_state());
+        handle.isr_on();
+        // the state it starts in is not an event
+        let bit = 1 << index;
+        self.reported = self.reported & !bit | self.snapshot() & bit;
+
+        Ok(PinSlot {
+            handle: Some(handle),
+        })
+    }
+
+    /// The debounced state of all the pins in the group, bit index = the index of the pin in the group
+    /// Each bit is updated by the interrupt handler as edges are accepted,
+    /// which makes it cheap enough to check several inputs at once (e.g. chorded keys)
+    pub fn snapshot(&self) -> u64 {
+        self.state.snapshot()
+    }
+}
+
+impl<Debouncer> LeveledEdgeGroup<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    pub fn new() -> Self {
+        Self::with_pins(Vec::new())
+    }
+
+    /// Add a pin to the group, returns its index (the bit it occupies in `snapshot`)
+    /// The index is the lowest one free, a pin added after a `remove` takes the index of the removed pin.
+    /// callback: Called from the interrupt handler after the group state was updated,
+    /// and after the group callback (see `on_change`)
+    /// Fails with `ESP_ERR_NO_MEM` if the group already has `MAX_PINS` pins.
+    pub fn add(
+        &mut self,
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        callback: impl FnMut(bool) + 'static,
+    ) -> Result<usize, EspError> {
+        let free = self.pins.iter().position(|slot| slot.handle.is_none());
+        if free.is_none() && self.pins.len() == Self::MAX_PINS {
+            return Err(EspError::from(ESP_ERR_NO_MEM).unwrap());
+        }
+
+        let index = free.unwrap_or(self.pins.len());
+        let slot = self.attach(index, gpio, debouncer, callback)?;
+        match free {
+            Some(_) => self.pins[index] = slot,
+            None => self.pins.push(slot),
+        }
+
+        Ok(index)
+    }
+
+    /// Take the pin at `index` out of the group, for input modules that can be unplugged
+    /// Its handle is dropped first, so its interrupt handler is gone before its bit is cleared
+    /// (in a critical section the handlers of the other pins take too), no handler sees it half removed.
+    /// The chords with the pin in them are dropped as well, the other pins keep their index,
+    /// and the pin drops out of `snapshot` without an `InputSource` event.
+    /// Fails with `ESP_ERR_INVALID_ARG` if there is no pin at `index`.
+    pub fn remove(&mut self, index: usize) -> Result<(), EspError> {
+        let handle = self
+            .pins
+            .get_mut(index)
+            .and_then(|slot| slot.handle.take())
+            .ok_or_else(|| EspError::from(ESP_ERR_INVALID_ARG).unwrap())?;
+        drop(handle);
+
+        let bit = 1 << index;
+        let state = &self.state;
+        let removed = state.with_shared(|shared| {
+            state.set(index, false);
+            shared.pressed_at[index] = 0;
+
+            let (removed, kept): (Vec<_>, Vec<_>) = shared
+                .detectors
+                .drain(..)
+                .partition(|chord| chord.mask & bit != 0);
+            shared.detectors = kept;
+            removed
+        });
+        self.reported &= !bit;
+
+        // dropped here rather than in the critical section
+        drop(removed);
+
+        while let Some(PinSlot { handle: None }) = self.pins.last() {
+            self.pins.pop();
+        }
+
+        Ok(())
+    }
+}
+
+impl<Debouncer, const N: usize> FixedLeveledEdgeGroup<Debouncer, N>
+where
+    Debouncer: Debounce,
+{
+    const FITS: () = assert!(N <= 64, "a LeveledEdgeGroup holds 64 pins at most");
+
+    /// Create a group of the `N` pins, pin `i` of the array gets index (bit) `i`
+    /// More than `MAX_PINS` pins fails to compile. Use `on_change` or `snapshot` to follow them,
+    /// the pins don't have callbacks of their own here.
+    /// If one of the handles fails, its error is returned and all the pins are dropped
+    /// (the handles made before it too).
+    pub fn from_pins(pins: [(GpioPin<Input>, Debouncer); N]) -> Result<Self, EspError> {
+        let () = Self::FITS;
+
+        let mut group = LeveledEdgeGroup::with_pins(());
+        let mut index = 0;
+        let mut error = None;
+        // made in order, the pins after a failed one aren't made at all
+        let slots = pins.map(|(gpio, debouncer)| {
+            let slot = match error {
+                None => group
+                    .attach(index, gpio, debouncer, |_| {})
+                    .map_err(|err| error = Some(err))
+                    .ok(),
+                Some(_) => None,
+            };
+            index += 1;
+            slot
+        });
+
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        Ok(LeveledEdgeGroup {
+            pins: slots.map(|slot| slot.unwrap()),
+            state: group.state,
+            reported: group.reported,
+            _debouncer: PhantomData,
+        })
+    }
+}
+
+impl<Debouncer, Pins> LeveledEdgeGroup<Debouncer, Pins>
+where
+    Debouncer: Debounce,
+    Pins: AsRef<[PinSlot<Debouncer>]>,
+{
+    /// Call `callback` when all the pins in `mask` (bits as in `snapshot`) are held active together,
+    /// with the last one pressed at most `window` after the first. E.g. `1 << a | 1 << b` for A+B.
+    ///
+    /// Fires once per chord, it has to be released (any of its keys) before it can fire again.
+    /// Keys pressed too far apart, a chord that is never completed, or a key released before
+    /// the other is pressed don't fire at all. The pins' own callbacks still get every edge.
+    /// callback: Called from the interrupt handler of the key that completed the chord
+    /// Fails with `ESP_ERR_INVALID_ARG` if `mask` is empty or has bits of pins not in the group.
+    pub fn on_chord(
+        &mut self,
+        mask: u64,
+        window: Duration,
+        callback: impl FnMut() + 'static,
+    ) -> Result<(), EspError> {
+        let added = self
+            .pins
+            .as_ref()
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.handle.is_some())
+            .fold(0u64, |added, (index, _)| added | 1 << index);
+        if mask == 0 || mask & !added != 0 {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        let detector = ChordDetector {
+            mask,
+            window: window_micros(window),
+            fired: false,
+            pending: false,
+            callback: Box::new(callback),
+        };
+
+        self.state
+            .with_shared(|shared| shared.detectors.push(detector));
+
+        Ok(())
+    }
+
+    /// Call `callback` with the whole `snapshot` on every accepted edge of any pin in the group,
+    /// for state machines that care about the combination of the inputs rather than single pins.
+    /// Bit `i` is the pin at index `i` of the group, set while it is active. Replaces the previous one.
+    /// callback: Called from the interrupt handler of the pin that changed, after its bit was updated
+    /// and before its own callback, so it must be isr safe (no blocking, allocating or logging).
+    pub fn on_change(&mut self, callback: impl FnMut(u64) + 'static) {
+        let callback: Box<dyn FnMut(u64)> = Box::new(callback);
+        let old = self
+            .state
+            .with_shared(|shared| shared.on_change.replace(callback));
+
+        // dropped here rather than in the critical section
+        drop(old);
+    }
+
+    /// Call `callback` with the `snapshot` once the group was quiet for a moment, instead of once per edge:
+    /// the first edge starts a one shot timer of `window`, and the edges of any pin until it expires
+    /// go into the same batch, delivered as the final bitmask. Two keys pressed a few ms apart come
+    /// as one change with both bits set, so a chord or combo is seen whole, never half pressed.
+    ///
+    /// The price is latency: every batch is delivered `window` after its first edge, even a lone press.
+    /// A short window (5-20ms) is enough for keys meant to go down together, a longer one merges
+    /// quick separate presses as well. A pin that goes and comes back within the window is not in it.
+    /// callback: Called from the esp_timer task, not the interrupt handler, so it may block or log
+    /// (briefly, it holds up the other esp_timer callbacks). Replaces the previous one.
+    pub fn on_batch(
+        &mut self,
+        window: Duration,
+        mut callback: impl FnMut(u64) + Send + 'static,
+    ) -> Result<(), EspError> {
+        // the timer lives in the same state as the bits, it is deleted before they go away
+        let bits = &self.state.bits as *const [AtomicU32; 2] as usize;
+        let timer = EspTimer::new(move || {
+            let bits = unsafe { &*(bits as *const [AtomicU32; 2]) };
+            callback(GroupState::load(bits));
+        })?;
+
+        let batch = Batch {
+            timer,
+            window_us: window_micros(window).max(1) as u64,
+        };
+        let old = self.state.with_shared(|shared| shared.batch.replace(batch));
+
+        // dropped here rather than in the critical section
+        drop(old);
+
+        Ok(())
+    }
+
+    fn handles(&self) -> impl Iterator<Item = &LeveledEdge<Debouncer, GroupCallback>> {
+        self.pins
+            .as_ref()
+            .iter()
+            .filter_map(|slot| slot.handle.as_deref())
+    }
+
+    /// The number of pins in the group, after a `remove` there can be indexes above it
+    pub fn len(&self) -> usize {
+        self.handles().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles().next().is_none()
+    }
+
+    /// The pin at `index`, `None` if there is none (or it was removed)
+    pub fn get(&self, index: usize) -> Option<&LeveledEdge<Debouncer, GroupCallback>> {
+        self.pins
+            .as_ref()
+            .get(index)
+            .and_then(|slot| slot.handle.as_deref())
+    }
+}
+
+impl<Debouncer, Pins> LeveledEdgeGroup<Debouncer, Pins>
+where
+    Debouncer: Debounce,
+    Pins: AsRef<[PinSlot<Debouncer>]> + AsMut<[PinSlot<Debouncer>]>,
+{
+    /// Hold off the interrupts of every pin in the group until the guard is dropped, for a section
+    /// that has to see the whole group stand still, e.g. reading `snapshot` together with state
+    /// the callbacks also touch. The guard derefs to the group for reading it meanwhile.
+    ///
+    /// An edge that comes in meanwhile isn't lost: once the guard is dropped every pin is resynced
+    /// on its own before its interrupt is enabled again, a pin found at the other level than it was
+    /// tracked at gets the edge it missed (as with `verify_and_correct`, per its `MissedEdgePolicy`)
+    /// and its bit follows. A pin that changed and went back while held off has nothing to catch up on.
+    /// Pins that were disabled before are left alone and stay disabled.
+    /// A pin whose debouncer confirms edges by polling (`Debounce::poll_confirm`) and is in the middle
+    /// of it is not resynced, its sampling goes on and enables its interrupt once it's done.
+    pub fn disable_all_guard(&mut self) -> GroupDisableGuard<'_, Debouncer, Pins> {
+        for handle in self.handles() {
+            handle.isr_off();
+        }
+
+        GroupDisableGuard { group: self }
+    }
+}
+
+/// The interrupts of a whole group held off, see `LeveledEdgeGroup::disable_all_guard`
+pub struct GroupDisableGuard<'a, Debouncer, Pins>
+where
+    Debouncer: Debounce,
+    Pins: AsRef<[PinSlot<Debouncer>]> + AsMut<[PinSlot<Debouncer>]>,
+{
+    group: &'a mut LeveledEdgeGroup<Debouncer, Pins>,
+}
+
+impl<Debouncer, Pins> Deref for GroupDisableGuard<'_, Debouncer, Pins>
+where
+    Debouncer: Debounce,
+    Pins: AsRef<[PinSlot<Debouncer>]> + AsMut<[PinSlot<Debouncer>]>,
+{
+    type Target = LeveledEdgeGroup<Debouncer, Pins>;
+
+    fn deref(&self) -> &Self::Target {
+        self.group
+    }
+}
+
+impl<Debouncer, Pins> Drop for GroupDisableGuard<'_, Debouncer, Pins>
+where
+    Debouncer: Debounce,
+    Pins: AsRef<[PinSlot<Debouncer>]> + AsMut<[PinSlot<Debouncer>]>,
+{
+    fn drop(&mut self) {
+        let group = &mut *self.group;
+
+        let pins = group.pins.as_mut().iter_mut().enumerate();
+        group
+            .state
+            .catch_up(pins.filter_map(|(index, slot)| Some((index, slot.handle.as_deref_mut()?))));
+
+        for handle in group.handles() {
+            handle.isr_on();
+        }
+    }
+}
+
+impl<Debouncer> Default for LeveledEdgeGroup<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leveled_edge::missed_edge;
+
+    #[test]
+    fn chords_are_called_after_the_lock() {
+        let state = GroupState::new();
+        let shared = unsafe { &mut *state.shared.get() };
+        shared.detectors.push(ChordDetector {
+            mask: 0b11,
+            window: 50_000,
+            fired: false,
+            pending: false,
+            callback: Box::new(|| {}),
+        });
+
+        GroupState::detect_chords(shared, 0, true, 0b01, 0);
+        assert!(!shared.detectors[0].pending);
+        // the second key within the window completes it, it's left for `dispatch` to call
+        GroupState::detect_chords(shared, 1, true, 0b11, 20_000);
+        assert!(shared.detectors[0].pending && shared.detectors[0].fired);
+    }
+
+    /// A pin of the group as the guard sees it
+    struct HeldPin {
+        tracked: bool,
+        level: bool,
+        polling: bool,
+        enabled: bool,
+        delivered: u32,
+    }
+
+    impl CatchUp for HeldPin {
+        fn is_enabled(&self) -> bool {
+            self.enabled
+        }
+
+        fn catch_up(&mut self) -> bool {
+            if let Some(level) = missed_edge(self.tracked, self.level, self.polling) {
+                self.tracked = level;
+                self.delivered += 1;
+            }
+            self.tracked
+        }
+    }
+
+    #[test]
+    fn guard_catches_up_per_pin() {
+        let state = GroupState::new();
+        state.set(0, true);
+
+        let pin = |tracked, level, polling, enabled| HeldPin {
+            tracked,
+            level,
+            polling,
+            enabled,
+            delivered: 0,
+        };
+        // while held off: pin 0 was released and pressed again, pin 1 pressed, pin 2 pressed
+        // as well but its debouncer is still sampling it, and pin 3 pressed while disabled
+        let mut pins = [
+            pin(true, true, false, true),
+            pin(false, true, false, true),
+            pin(false, true, true, true),
+            pin(false, true, false, false),
+        ];
+        state.catch_up(pins.iter_mut().enumerate());
+
+        assert_eq!(state.snapshot(), 0b0011);
+        let delivered: Vec<_> = pins.iter().map(|pin| pin.delivered).collect();
+        assert_eq!(delivered, [0, 1, 0, 0]);
+    }
+}
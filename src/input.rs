@@ -0,0 +1,84 @@
+use crate::callback::EdgeCallback;
+use crate::debounce::Debounce;
+use crate::encoder::{EncoderBank, RotaryDirection, RotaryEncoder};
+use crate::group::LeveledEdgeGroup;
+use crate::leveled_edge::LeveledEdge;
+use esp_idf_hal::delay::TickType;
+
+/// An input event in the terms of a UI, whatever it came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    /// A button (or any other input) became active (`pressed`) or inactive
+    Button { id: u32, pressed: bool },
+    /// An encoder moved one step
+    Rotate { id: u32, direction: RotaryDirection },
+}
+
+/// Anything a UI layer can pull input events from, so it can treat buttons, encoders and groups alike
+///
+/// `next` never blocks, it returns `None` when there is nothing new. The `id` of the events is
+/// the handle's user data for a `LeveledEdge`, the index for an `EncoderBank` or a `LeveledEdgeGroup`,
+/// and 0 for a `RotaryEncoder`.
+pub trait InputSource {
+    fn next(&mut self) -> Option<InputEvent>;
+}
+
+/// Built on `take_event`, so edges that came in between two calls coalesce into the last one
+impl<Debouncer, Func> InputSource for LeveledEdge<Debouncer, Func>
+where
+    Debouncer: Debounce,
+    Func: EdgeCallback,
+{
+    fn next(&mut self) -> Option<InputEvent> {
+        self.take_event().map(|pressed| InputEvent::Button {
+            id: self.user_data(),
+            pressed,
+        })
+    }
+}
+
+impl<Debouncer> InputSource for RotaryEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    fn next(&mut self) -> Option<InputEvent> {
+        self.wait_on_direction(TickType(0))
+            .map(|direction| InputEvent::Rotate { id: 0, direction })
+    }
+}
+
+impl<Debouncer> InputSource for EncoderBank<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    fn next(&mut self) -> Option<InputEvent> {
+        self.next_event(TickType(0))
+            .map(|(index, event)| InputEvent::Rotate {
+                id: index as u32,
+                direction: event.direction,
+            })
+    }
+}
+
+/// Compares the group's snapshot to the one seen by the previous events,
+/// a pin that changed twice in between is not reported at all
+impl<Debouncer, Pins> InputSource for LeveledEdgeGroup<Debouncer, Pins>
+where
+    Debouncer: Debounce,
+{
+    fn next(&mut self) -> Option<InputEvent> {
+        let snapshot = self.snapshot();
+        let changed = snapshot ^ self.reported;
+        if changed == 0 {
+            return None;
+        }
+
+        let index = changed.trailing_zeros();
+        self.reported ^= 1 << index;
+
+        Some(InputEvent::Button {
+            id: index,
+            pressed: snapshot & (1 << index) != 0,
+        })
+    }
+}
@@ -0,0 +1,90 @@
+use crate::callback::{EdgeAction, EdgeCallback};
+use crate::debounce::Debounce;
+use crate::leveled_edge::LeveledEdge;
+use esp_idf_hal::gpio::{GpioPin, Input};
+use esp_idf_sys::EspError;
+
+/// A debounced button as a key of a keyboard, see `LeveledEdge::as_key`
+///
+/// `K` is whatever the keyboard code tells its keys apart by: a HID usage id (`u8`) for a report
+/// builder, a `keyberon::key_code::KeyCode`, or the `(row, column)` of the key for the `keyberon`
+/// layout engine (with the `keyberon` feature it converts to a `keyberon::layout::Event`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent<K> {
+    /// The button went to its active level
+    Press(K),
+    /// The button went back to its inactive level
+    Release(K),
+}
+
+impl<K: Copy> KeyEvent<K> {
+    pub fn key(&self) -> K {
+        match self {
+            KeyEvent::Press(key) | KeyEvent::Release(key) => *key,
+        }
+    }
+
+    pub fn is_press(&self) -> bool {
+        matches!(self, KeyEvent::Press(_))
+    }
+}
+
+#[cfg(feature = "keyberon")]
+impl From<KeyEvent<(u8, u8)>> for keyberon::layout::Event {
+    fn from(event: KeyEvent<(u8, u8)>) -> Self {
+        match event {
+            KeyEvent::Press((row, column)) => keyberon::layout::Event::Press(row, column),
+            KeyEvent::Release((row, column)) => keyberon::layout::Event::Release(row, column),
+        }
+    }
+}
+
+/// A callback that turns the accepted edges into key events of `key`, see `LeveledEdge::as_key`
+pub struct AsKey<K, F>
+where
+    K: Copy,
+    F: FnMut(KeyEvent<K>),
+{
+    pub key: K,
+    pub callback: F,
+}
+
+impl<K, F> EdgeCallback for AsKey<K, F>
+where
+    K: Copy,
+    F: FnMut(KeyEvent<K>),
+{
+    #[inline(always)]
+    fn on_edge(&mut self, active: bool, _user_data: u32) -> EdgeAction {
+        let event = match active {
+            true => KeyEvent::Press(self.key),
+            false => KeyEvent::Release(self.key),
+        };
+        (self.callback)(event);
+
+        EdgeAction::Continue
+    }
+}
+
+impl<Debouncer, K, F> LeveledEdge<Debouncer, AsKey<K, F>>
+where
+    Debouncer: Debounce,
+    K: Copy,
+    F: FnMut(KeyEvent<K>),
+{
+    /// A button that reports as `key` of a keyboard: a press on every edge to the active level and
+    /// a release on every edge back, for feeding a HID report builder or the `keyberon` layout.
+    /// The handle tracks the level, so presses and releases always alternate, even across a missed edge.
+    /// `callback` is called from the interrupt handler like any other callback, typically it only
+    /// sends the event to the keyboard task (e.g. an `IsrQueue<KeyEvent<K>>`).
+    /// A press is the pin going high, for a button with a pull up build an `AsKey` with `builder`
+    /// and `LeveledEdgeBuilder::active(Level::Low)`.
+    pub fn as_key(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        key: K,
+        callback: F,
+    ) -> Result<Box<Self>, EspError> {
+        Self::new(gpio, debouncer, AsKey { key, callback })
+    }
+}
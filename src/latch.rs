@@ -0,0 +1,62 @@
+use crate::debounce::Debounce;
+use crate::edge::EdgeFilter;
+use crate::leveled_edge::{LeveledEdge, NoCallback};
+use esp_idf_hal::gpio::{GpioPin, Input, Output, Pin};
+use esp_idf_sys::{gpio_set_level, EspError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+type LatchCallback = Box<dyn FnMut(bool)>;
+
+/// A push-on / push-off latch, flipping an output on every accepted edge of an input
+/// Created with `LeveledEdge::toggle_output`
+pub struct Latch<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    latched: Arc<AtomicBool>,
+    _interrupt: Box<LeveledEdge<Debouncer, LatchCallback>>,
+}
+
+impl<Debouncer> Latch<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// The current state of the latch, which is also the level of the output
+    pub fn latched(&self) -> bool {
+        self.latched.load(Ordering::Acquire)
+    }
+}
+
+impl<Debouncer> LeveledEdge<Debouncer, NoCallback>
+where
+    Debouncer: Debounce,
+{
+    /// Flip `output` on every accepted `edge` of `input`, e.g. `EdgeFilter::Falling` for a button
+    /// to ground. This is the classic soft power switch, the latch starts released (output low).
+    pub fn toggle_output(
+        input: GpioPin<Input>,
+        debouncer: Debouncer,
+        output: GpioPin<Output>,
+        edge: EdgeFilter,
+    ) -> Result<Latch<Debouncer>, EspError> {
+        let latched = Arc::new(AtomicBool::new(false));
+        unsafe { gpio_set_level(output.pin(), 0) };
+
+        let latched_clone = latched.clone();
+        let on_edge: LatchCallback = Box::new(move |level| {
+            if !edge.matches(level) {
+                return;
+            }
+
+            let state = !latched_clone.load(Ordering::Relaxed);
+            latched_clone.store(state, Ordering::Release);
+            unsafe { gpio_set_level(output.pin(), state as u32) };
+        });
+
+        Ok(Latch {
+            latched,
+            _interrupt: LeveledEdge::new(input, debouncer, on_edge)?,
+        })
+    }
+}
@@ -1,24 +1,160 @@
+use crate::builder::LeveledEdgeBuilder;
+use crate::callback::{EdgeAction, EdgeCallback, EdgeContext, EdgeCounter, RawCallback, Tagged};
+use crate::clock::Clock;
+use crate::config::{ConfigDebounce, ConfigError, DebounceKind, LeveledEdgeConfig};
 use crate::debounce::*;
+use crate::deferred::DeferredTask;
+use crate::edge::{EdgeFilter, Level, MissedEdgePolicy};
+use crate::encoder::SampledPin;
+use crate::flags::EdgeFlags;
+use crate::follower::Follower;
+use crate::gate::Gate;
+use crate::pins::{check_interrupt_capable, InterruptCapable, PinConfig, PullMode};
+#[cfg(feature = "profiling")]
+use crate::profile::IsrProfile;
+use crate::queue::{IsrQueue, SendPolicy};
+#[cfg(feature = "registry")]
+use crate::registry;
+use crate::seqlock::SeqLock;
+use crate::service::{install_service, release_service, IsrConfig};
+use crate::settling::SettlingMeter;
+use crate::sink::{notify_from_isr, EdgeEvent, Sink};
+use crate::state::HandleState;
+#[cfg(feature = "stats")]
+use crate::stats::{IntervalHistogram, HISTOGRAM_BUCKETS};
+use crate::telemetry::{Counters, LeveledEdgeTelemetry};
+use crate::timer::EspTimer;
+#[cfg(feature = "trace")]
+use crate::trace::{TraceEntry, TraceRing};
+use crate::unstable::UnstableDetector;
 use embedded_hal::digital::v2::InputPin;
-use esp_idf_hal::gpio::{GpioPin, Input, InterruptType, Pin};
-use esp_idf_sys::{esp, gpio_set_intr_type, EspError, ESP_ERR_INVALID_STATE, ESP_OK};
+use esp_idf_hal::gpio::{GpioPin, Input, Output, Pin};
+use esp_idf_hal::interrupt;
+use esp_idf_sys::{
+    esp, esp_rom_delay_us, gpio_get_level, gpio_intr_disable, gpio_intr_enable,
+    gpio_mode_t_GPIO_MODE_INPUT, gpio_set_direction, gpio_set_intr_type, gpio_set_pull_mode,
+    tskTaskControlBlock, EspError, TaskHandle_t, ESP_ERR_INVALID_ARG, ESP_ERR_INVALID_STATE,
+    ESP_OK,
+};
+use std::hint::spin_loop;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU8, Ordering};
+use std::time::Duration;
+
+/// The callback type of handles that don't need a user callback (e.g. `LeveledEdge::follow`)
+pub type NoCallback = fn(bool);
+
+fn no_callback(_state: bool) {}
+
+/// The values of `LeveledEdge::event`, the level of the edge is kept as `EVENT_INACTIVE + active`
+const NO_EVENT: u8 = 0;
+const EVENT_INACTIVE: u8 = 1;
+
+/// A handle called through a plain function pointer, see `RawCallback` for when it pays off
+pub type RawLeveledEdge<Debouncer> = LeveledEdge<Debouncer, RawCallback>;
+
+/// Reads of the pin the handler does at most before giving up on it settling
+const SETTLE_MAX_READS: u32 = 16;
+/// Consecutive reads of the same level for the pin to count as settled
+const SETTLE_STABLE_READS: u32 = 3;
+
+/// Follow the pin from `state` until it reads the same level `SETTLE_STABLE_READS` times in a row,
+/// coalescing every change seen on the way, and return that level.
+/// Bounded by `SETTLE_MAX_READS` so a pin that never settles can't lock up the isr,
+/// in which case the last level read is returned and the level interrupt fires again for the rest.
+#[inline(always)]
+#[link_section = ".iram1.leveled_edge_settle"]
+pub(crate) unsafe fn settle(pin: i32, state: bool) -> bool {
+    settle_reads(state, || gpio_get_level(pin) != 0)
+}
+
+/// `settle` with the pin reads coming from `read`
+#[inline(always)]
+pub(crate) fn settle_reads(mut state: bool, mut read: impl FnMut() -> bool) -> bool {
+    let mut stable = 0;
+
+    for _ in 0..SETTLE_MAX_READS {
+        let level = read();
+
+        if level == state {
+            stable += 1;
+            if stable == SETTLE_STABLE_READS {
+                break;
+            }
+        } else {
+            state = level;
+            stable = 0;
+        }
+    }
+
+    state
+}
+
+/// The level a handle missed the edge to: the pin is at `level`, the isr tracked `tracked`.
+/// A polling debouncer in the middle of sampling is settling that very level, that's left to it
+#[inline(always)]
+pub(crate) fn missed_edge(tracked: bool, level: bool, polling: bool) -> Option<bool> {
+    (level != tracked && !polling).then_some(level)
+}
 
 /// A leveled edge interrupt handle
 pub struct LeveledEdge<Debouncer, Func>
 where
     Debouncer: Debounce,
-    Func: FnMut(bool) -> (),
+    Func: EdgeCallback,
 {
-    gpio: GpioPin<Input>,
-    pin_state: bool,
-    debouncer: Debouncer,
-    callback: *mut Func,
+    /// Dropped by hand in `Drop`, unless `detach` took it
+    gpio: ManuallyDrop<GpioPin<Input>>,
+    state: HandleState,
+    active: Level,
+    /// The last accepted level and when it was accepted, published together
+    last_edge: SeqLock<(bool, i64)>,
+    muted: AtomicBool,
+    /// The last delivered edge not taken yet, see `take_event`
+    event: AtomicU8,
+    isr_config: IsrConfig,
+    holds_service: bool,
+    output: Option<Follower>,
+    sinks: Vec<Sink>,
+    missed_edge_policy: MissedEdgePolicy,
+    #[cfg(feature = "stats")]
+    histogram: IntervalHistogram,
+    #[cfg(feature = "trace")]
+    trace: TraceRing,
+    #[cfg(feature = "profiling")]
+    profile: IsrProfile,
+    /// Same as `gpio`
+    debouncer: ManuallyDrop<Debouncer>,
+    callback: Option<Func>,
+    _deferred: Option<DeferredTask>,
+    sampled_pin: Option<SampledPin>,
+    user_data: u32,
+    gate: Option<Box<dyn Gate>>,
+    pull: Option<PullMode>,
+    poller: Option<Poller>,
+    settling: Option<SettlingMeter>,
+    /// What the timestamps read back are translated with, `esp_timer` time as is if `None`
+    clock: Option<Box<dyn Clock>>,
+    /// The level the pin has to be at for `arm`, see `LeveledEdgeBuilder::require_initial_level`
+    initial_level: Option<bool>,
+    /// See `LeveledEdgeBuilder::emit_initial_state`
+    emit_initial_state: bool,
+    unstable: Option<UnstableDetector>,
+    /// The task blocked in `wait_for_level`, if any
+    waiter: AtomicPtr<tskTaskControlBlock>,
+    arming_delay: Duration,
+    armed: bool,
+    /// See `LeveledEdgeBuilder::disable_during_isr`
+    disable_during_isr: bool,
+    /// `detach` tore the handle down and took the pin and the debouncer already
+    detached: bool,
 }
 
 impl<Debouncer, Func> LeveledEdge<Debouncer, Func>
 where
     Debouncer: Debounce,
-    Func: FnMut(bool),
+    Func: EdgeCallback,
 {
     /// Create a new instance of `LeveledEdge`
     /// debouncer: The debouncer to use, if no debouncer is needed, use `NoDebounce`
@@ -29,6 +165,8 @@ where
     /// - `true` if the pin is high
     /// - `false` if the pin is low
     ///
+    /// Use `LeveledEdge::builder` to get `true` = pressed for active low inputs instead
+    ///
     /// # Example - creating an interrupt on pin 4 button to light led on pin 18
     /// ```
     /// use embedded_hal::digital::v2::{InputPin, OutputPin, PinState};
@@ -65,36 +203,755 @@ where
         debouncer: Debouncer,
         callback: Func,
     ) -> Result<Box<Self>, EspError> {
-        let pin_state = gpio.is_high()?;
+        Self::builder(gpio, debouncer, callback).build()
+    }
 
-        let mut this = Box::new(LeveledEdge {
+    /// Start building a handle with non default options, see `LeveledEdgeBuilder`
+    pub fn builder(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        callback: Func,
+    ) -> LeveledEdgeBuilder<Debouncer, Func> {
+        LeveledEdgeBuilder::new(gpio, debouncer, callback)
+    }
+
+    pub(crate) fn create(
+        builder: LeveledEdgeBuilder<Debouncer, Func>,
+    ) -> Result<Box<Self>, EspError> {
+        let LeveledEdgeBuilder {
             gpio,
-            pin_state,
             debouncer,
-            callback: Box::into_raw(Box::new(callback)),
+            callback,
+            active,
+            isr_config,
+            output,
+            sinks,
+            missed_edge_policy,
+            deferred,
+            sampled_pin,
+            user_data,
+            label,
+            disable_during_isr,
+            arming_delay,
+            gate,
+            #[cfg(feature = "trace")]
+            trace_depth,
+            pull,
+            settling,
+            clock,
+            initial_level,
+            emit_initial_state,
+            unstable,
+            on_unstable,
+        } = builder;
+
+        let pin = gpio.pin();
+        check_interrupt_capable(pin)?;
+        check_timer()?;
+        if sinks.len() > Sink::MAX_SINKS {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        // these return from the isr without re-arming, see `LeveledEdgeBuilder::disable_during_isr`
+        let rearms_later = debouncer.poll_confirm().is_some()
+            || deferred.as_ref().and_then(DeferredTask::hold_off).is_some();
+        if rearms_later && !disable_during_isr {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        let pin_state = gpio.is_high()?;
+
+        if let Some(output) = &output {
+            output.set(pin_state);
+        }
+
+        let mut this = Box::new(LeveledEdge {
+            gpio: ManuallyDrop::new(gpio),
+            state: HandleState::new(
+                pin,
+                label,
+                pin_state,
+                deferred.as_ref().and_then(DeferredTask::hold_off),
+            ),
+            active,
+            last_edge: SeqLock::new((pin_state, micros())),
+            muted: AtomicBool::new(false),
+            event: AtomicU8::new(NO_EVENT),
+            isr_config,
+            holds_service: false,
+            output,
+            sinks,
+            missed_edge_policy,
+            #[cfg(feature = "stats")]
+            histogram: IntervalHistogram::new(),
+            #[cfg(feature = "trace")]
+            trace: TraceRing::new(trace_depth),
+            #[cfg(feature = "profiling")]
+            profile: IsrProfile::new(),
+            debouncer: ManuallyDrop::new(debouncer),
+            callback: Some(callback),
+            _deferred: deferred,
+            sampled_pin,
+            user_data,
+            gate,
+            pull,
+            poller: None,
+            settling,
+            clock,
+            initial_level,
+            emit_initial_state,
+            unstable: unstable
+                .map(|(threshold, window)| UnstableDetector::new(threshold, window, on_unstable)),
+            waiter: AtomicPtr::new(ptr::null_mut()),
+            arming_delay,
+            armed: false,
+            disable_during_isr,
+            detached: false,
         });
 
-        this.install_isr()?;
+        if let Some((interval, required_stable)) = this.debouncer.poll_confirm() {
+            // a plain fn pointer and address, so the timer closure doesn't borrow the handle's types
+            let sample: unsafe fn(usize) = Self::poll_sample;
+            let handle = this.as_mut() as *mut Self as usize;
+            let timer = EspTimer::new(move || unsafe { sample(handle) })?;
+
+            this.poller = Some(Poller::new(timer, interval, required_stable));
+        }
 
         Ok(this)
     }
 
-    ///Install the interrupt handler on the pin supplied
-    fn install_isr(&mut self) -> Result<(), EspError> {
-        let next_intr = match self.pin_state {
-            true => InterruptType::LowLevel,
-            false => InterruptType::HighLevel,
+    /// Same as `new`, but the interrupt is not installed until `arm` is called, see `LeveledEdgeBuilder::prepare`
+    pub fn prepare(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        callback: Func,
+    ) -> Result<Box<Self>, EspError> {
+        Self::builder(gpio, debouncer, callback).prepare()
+    }
+
+    /// Install the interrupt of a handle made with `prepare` and start getting edges,
+    /// from the level the pin is at now and not the one it was at when prepared.
+    /// Fails with `ESP_ERR_INVALID_STATE` if the handle is armed already,
+    /// or if the pin is not at the level set with `LeveledEdgeBuilder::require_initial_level`.
+    pub fn arm(&mut self) -> Result<(), EspError> {
+        if self.armed {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+        }
+
+        if let Some(level) = self.initial_level {
+            if self.gpio.is_high()? != level {
+                return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+            }
+        }
+
+        self.install_isr()?;
+        self.armed = true;
+
+        if self.emit_initial_state {
+            self.emit_current_state();
+        }
+
+        #[cfg(feature = "registry")]
+        registry::register(&self.state);
+
+        Ok(())
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Same as `new`, but takes a typed pin (e.g. `pins.gpio4.into_input()?`) instead of a degraded one,
+    /// so pins that can't be used for interrupts are rejected at compile time
+    pub fn new_typed(
+        gpio: impl InterruptCapable,
+        debouncer: Debouncer,
+        callback: Func,
+    ) -> Result<Box<Self>, EspError> {
+        Self::new(gpio.into_interrupt_pin(), debouncer, callback)
+    }
+
+    /// Set up a raw gpio number as an input and install the handle in one go, in the order that
+    /// doesn't produce phantom edges: interrupt off, input, pulls, and only then read the initial state.
+    /// Fails with `ESP_ERR_INVALID_ARG` if `gpio_num` can't be used for interrupts
+    /// (out of range for the chip, or one of the flash pins).
+    /// This is the way in for firmware that reads its pin assignment at runtime (from NVS, a config file..)
+    /// and has no typed pin from `Peripherals` to hand over.
+    ///
+    /// # Safety
+    /// The pin is taken by number, the caller guarantees it owns it exclusively:
+    /// nothing else may be using it, nor take it from `Peripherals` later (same as `GpioPin::new`)
+    #[doc(alias = "from_raw_gpio")]
+    pub unsafe fn from_pin_num(
+        gpio_num: i32,
+        config: PinConfig,
+        debouncer: Debouncer,
+        callback: Func,
+    ) -> Result<Box<Self>, EspError> {
+        Self::pin_num_builder(gpio_num, config, debouncer, callback)?.build()
+    }
+
+    /// Set up the pin of `from_pin_num`, and the builder of its handle
+    pub(crate) unsafe fn pin_num_builder(
+        gpio_num: i32,
+        config: PinConfig,
+        debouncer: Debouncer,
+        callback: Func,
+    ) -> Result<LeveledEdgeBuilder<Debouncer, Func>, EspError> {
+        check_interrupt_capable(gpio_num)?;
+
+        esp!(gpio_intr_disable(gpio_num))?;
+        esp!(gpio_set_direction(gpio_num, gpio_mode_t_GPIO_MODE_INPUT))?;
+        esp!(gpio_set_pull_mode(gpio_num, config.pull.into()))?;
+        // give the pull a moment to charge the line before the initial state is read
+        esp_rom_delay_us(10);
+
+        Ok(Self::builder(GpioPin::new(gpio_num), debouncer, callback)
+            .active(config.active)
+            .pull(config.pull))
+    }
+
+    /// The name of the debouncer's strategy (`"classic"`, `"filter"`...), see `Debounce::strategy_name`
+    pub fn debounce_strategy(&self) -> &'static str {
+        self.debouncer.strategy_name()
+    }
+
+    /// The id set with `LeveledEdgeBuilder::user_data`
+    pub fn user_data(&self) -> u32 {
+        self.user_data
+    }
+
+    /// The name set with `LeveledEdgeBuilder::label`
+    pub fn label(&self) -> Option<&'static str> {
+        self.state.label
+    }
+
+    /// The current state of the pin as tracked by the interrupt handler
+    /// - `true` if the pin is high
+    /// - `false` if the pin is low
+    pub fn current_state(&self) -> bool {
+        self.state.current_state()
+    }
+
+    /// The current state of the pin relative to the active level set on the builder
+    /// - `true` if the input is active (e.g. pressed)
+    /// - `false` if it is inactive
+    pub fn is_active(&self) -> bool {
+        self.active.is_active(self.current_state())
+    }
+
+    /// Microseconds passed since the last edge accepted by the debouncer
+    /// (or since the handle was created if no edge was accepted yet).
+    ///
+    /// Together with `current_state` this lets a polling task answer questions like
+    /// "has the door been open for more than 5 seconds" without its own timers.
+    pub fn time_in_state_micros(&self) -> i64 {
+        micros() - self.last_edge.read().1
+    }
+
+    /// The last accepted level and the microseconds since it was accepted, as one consistent pair
+    ///
+    /// Both are published by the interrupt handler in a single write and read back in a single read,
+    /// so the age always belongs to the level, even if an edge lands in between
+    /// (unlike calling `current_state` and `time_in_state_micros` one after the other).
+    /// The level is the debounced one, it can lag `current_state` while an edge is being settled.
+    pub fn state_snapshot(&self) -> (bool, i64) {
+        let (level, at) = self.last_edge.read();
+        (level, micros() - at)
+    }
+
+    /// When the last edge was accepted (or the handle created if none was yet), in microseconds
+    /// of the handle's clock, `esp_timer` time unless set with `LeveledEdgeBuilder::clock`
+    pub fn last_edge_timestamp(&self) -> i64 {
+        self.timestamp(self.last_edge.read().1)
+    }
+
+    fn timestamp(&self, esp_timer_us: i64) -> i64 {
+        match &self.clock {
+            Some(clock) => clock.translate(esp_timer_us),
+            None => esp_timer_us,
+        }
+    }
+
+    /// Attach a new callback in place of the current one, like esp-idf-hal's `PinDriver::subscribe`
+    /// but with the leveled edge handling underneath. The callback has to be of the handle's `Func` type,
+    /// a handle created with a `Box<dyn FnMut(bool)>` takes any closure.
+    /// The interrupt is held off while swapping, the old callback is dropped here, not in the isr.
+    pub fn subscribe(&mut self, callback: Func) {
+        drop(self.swap_callback(Some(callback)));
+    }
+
+    /// Detach the callback, edges are still tracked (and the follower output driven) without it
+    pub fn unsubscribe(&mut self) {
+        drop(self.swap_callback(None));
+    }
+
+    fn swap_callback(&mut self, callback: Option<Func>) -> Option<Func> {
+        self.isr_off();
+        let old = std::mem::replace(&mut self.callback, callback);
+        self.isr_on();
+
+        old
+    }
+
+    /// Replace the debouncer and the callback together, for mode switches where the whole behavior
+    /// of the pin changes. The interrupt is held off for the whole swap, so the isr never sees
+    /// the new debouncer with the old callback (or the other way around).
+    /// The state is then read again from the pin, as in `enable`, since the old debouncer's
+    /// idea of it doesn't carry over. A disabled handle stays disabled.
+    /// Returns the old debouncer and callback, dropped here and not in the isr if unused.
+    pub fn reconfigure(
+        &mut self,
+        debouncer: Debouncer,
+        callback: Func,
+    ) -> Result<(Debouncer, Option<Func>), EspError> {
+        self.isr_off();
+        let old_debouncer = std::mem::replace(&mut *self.debouncer, debouncer);
+        let old_callback = self.callback.replace(callback);
+
+        match self.is_enabled() {
+            true => self.resync_and_enable()?,
+            false => self.isr_on(),
+        }
+
+        Ok((old_debouncer, old_callback))
+    }
+
+    /// Block the calling task until the debounced level (as in `state_snapshot`, `true` = high) is `target`,
+    /// for sequential logic like "wait for the button to be released, 2 seconds at most" without a queue.
+    /// Returns `Ok(true)` right away if it is at `target` already, `Ok(false)` if `timeout` passed first.
+    /// Woken by the handler's task notification on every accepted edge, so the calling task shouldn't
+    /// wait on notifications of its own meanwhile (see `notify_task`), and only one task may wait at a time.
+    /// Fails with `ESP_ERR_INVALID_STATE` if the handle is not armed, it would never see an edge.
+    pub fn wait_for_level(&self, target: bool, timeout: Duration) -> Result<bool, EspError> {
+        if !self.armed {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+        }
+
+        let task = unsafe { esp_idf_sys::xTaskGetCurrentTaskHandle() };
+        self.waiter.store(task, Ordering::Release);
+
+        // an edge between checking the level and blocking still leaves the notification pending
+        let deadline = micros() + window_micros(timeout);
+        let reached = loop {
+            if self.last_edge.read().0 == target {
+                break true;
+            }
+
+            let left = deadline - micros();
+            if left <= 0 {
+                break false;
+            }
+
+            interrupt::task::wait_notification(Some(Duration::from_micros(left as u64)));
         };
 
-        esp!(unsafe { gpio_set_intr_type(self.gpio.pin(), next_intr.into()) })?;
+        self.waiter.store(ptr::null_mut(), Ordering::Release);
+        Ok(reached)
+    }
 
-        unsafe {
-            match esp_idf_sys::gpio_install_isr_service(0) {
-                ESP_OK | ESP_ERR_INVALID_STATE => {}
-                err => return Err(EspError::from(err).unwrap()),
+    /// Deliver every accepted edge straight to `task` with a task notification, the lightest way
+    /// to hand the level to a single dedicated task (no queue, no callback of its own).
+    /// The notification value is 1 for active and 0 for inactive, the task waits for it with
+    /// `esp_idf_hal::interrupt::task::wait_notification`. It is overwritten on every edge, so a task that
+    /// falls behind sees the latest level only, and as a task has a single notification value,
+    /// it should be the only thing notifying that task.
+    /// Added as one of the handle's sinks, fails with `ESP_ERR_INVALID_ARG` if there is no room left.
+    pub fn notify_task(&mut self, task: TaskHandle_t) -> Result<(), EspError> {
+        if self.sinks.len() >= Sink::MAX_SINKS {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        // the sinks belong to the isr, keep it out while adding one
+        self.isr_off();
+        self.sinks.push(Sink::Notify(task));
+        self.isr_on();
+
+        Ok(())
+    }
+
+    /// Replace the gate set with `LeveledEdgeBuilder::gate` (if any), e.g. to change modes at runtime
+    /// without tearing the handle down. `None` delivers every accepted edge again.
+    /// The interrupt is held off while swapping, the old gate is dropped here, not in the isr.
+    pub fn set_gate(&mut self, gate: Option<Box<dyn Gate>>) {
+        self.isr_off();
+        let old = std::mem::replace(&mut self.gate, gate);
+        self.isr_on();
+
+        drop(old);
+    }
+
+    /// Deliver only the edges that pass `filter` from now on, in place of the current gate.
+    /// The filter is relative to the active level like the callback's argument (`Rising` = to active).
+    ///
+    /// Only the delivery changes, the state is tracked and the trigger level toggled on every edge
+    /// as before, so `current_state` stays right. A callback that pairs presses with releases has to
+    /// expect an unmatched one around the switch, e.g. a press delivered before and its release filtered out.
+    pub fn set_edge_filter(&mut self, filter: EdgeFilter) {
+        self.set_gate(Some(Box::new(filter)));
+    }
+
+    /// The last delivered edge since the previous call (the callback's argument), cleared by taking it.
+    /// For a main loop that only wants to know "was it pressed since I last looked", no callback or queue.
+    /// Edges coalesce by design, whatever came in between two calls is replaced by the most recent one.
+    pub fn take_event(&self) -> Option<bool> {
+        match self.event.swap(NO_EVENT, Ordering::AcqRel) {
+            NO_EVENT => None,
+            event => Some(event != EVENT_INACTIVE),
+        }
+    }
+
+    /// Stop calling the callback (and driving the follower output), without stopping the interrupt.
+    /// Unlike disabling the interrupt, the handler keeps tracking the pin state and toggling the
+    /// trigger level, so the state is still correct when the handle is unmuted.
+    pub fn mute(&mut self) {
+        self.flags().mute();
+    }
+
+    /// Resume calling the callback after `mute`
+    pub fn unmute(&mut self) {
+        self.flags().unmute();
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.flags().is_muted()
+    }
+
+    /// Mute, unmute and read the state and counters without `&mut` and without any lock,
+    /// for toggling from a hot loop of another task, see `EdgeFlags` for what it guarantees.
+    pub fn flags(&self) -> EdgeFlags<'_> {
+        EdgeFlags {
+            muted: &self.muted,
+            enabled: &self.state.enabled,
+            pin_state: &self.state.pin_state,
+            counters: &self.state.counters,
+        }
+    }
+
+    /// All the counters of the handle in one snapshot, e.g. to ship to a dashboard
+    /// With the `serde` feature it can be serialized as is.
+    pub fn telemetry(&self) -> LeveledEdgeTelemetry {
+        self.state.counters.snapshot(
+            self.state.label,
+            self.current_state(),
+            self.time_in_state_micros(),
+        )
+    }
+
+    /// Raise the debounce window to `temporary` for the next `for_`, e.g. around switching a relay
+    /// that is known to inject noise on the line. Only does something if the debouncer supports it,
+    /// see `Debounce::boost`.
+    pub fn boost_debounce(&mut self, temporary: Duration, for_: Duration) {
+        // the debouncer belongs to the isr, keep it out while changing it
+        self.isr_off();
+        self.debouncer.boost(temporary, for_);
+        self.isr_on();
+    }
+
+    /// Histogram of the time between consecutive edges (accepted or not), see `HISTOGRAM_LIMITS_MICROS`
+    /// for the bucket bounds. Collected over the life of the handle, useful for grading how badly
+    /// a switch bounces in the field without streaming every edge off the device.
+    #[cfg(feature = "stats")]
+    pub fn histogram(&self) -> [u32; HISTOGRAM_BUCKETS] {
+        self.histogram.snapshot()
+    }
+
+    /// A debounce window just above the longest bounce seen so far (plus a quarter of it, rounded up
+    /// to the millisecond), for a setup wizard that has the user press the button a few times and then
+    /// builds the handle again with it.
+    ///
+    /// The bounce is timed from an accepted edge to the last edge the debouncer rejected after it,
+    /// so bounces are only seen while the current window is longer than them: sample with a generous
+    /// one (e.g. 50ms), and at a normal pace, a second press within the window counts as a bounce.
+    /// `None` until `RECOMMEND_MIN_EDGES` edges were accepted (a press and its release are two),
+    /// fewer say little about the tail of the bounce. Also `None` if the window would be as long as
+    /// the shortest time seen between two accepted edges, it would swallow real presses.
+    #[cfg(feature = "stats")]
+    pub fn recommend_debounce_time(&self) -> Option<Duration> {
+        self.histogram.recommend_debounce_time()
+    }
+
+    /// The last edges the handler saw (up to `LeveledEdgeBuilder::trace_depth`), oldest first.
+    /// A fixed window into the past for crash dumps and field debugging, not a stream to consume,
+    /// reading it doesn't clear it. Edges that settled back to where they were are not in it.
+    #[cfg(feature = "trace")]
+    pub fn trace(&self) -> Vec<TraceEntry> {
+        let mut entries = self.trace.snapshot();
+        for entry in &mut entries {
+            entry.label = self.state.label;
+            if self.clock.is_some() {
+                entry.timestamp = self.timestamp(entry.timestamp);
+            }
+        }
+
+        entries
+    }
+
+    /// The longest run of the interrupt handler so far in microseconds, callback, gate and sinks included,
+    /// to catch a callback that is too slow for an isr. Timing every run costs an extra `esp_timer` read
+    /// and a few stores per edge, which is why it is behind the `profiling` feature.
+    #[cfg(feature = "profiling")]
+    pub fn max_isr_micros(&self) -> i64 {
+        self.profile.max_micros()
+    }
+
+    /// The average run of the interrupt handler so far in microseconds, see `max_isr_micros`
+    #[cfg(feature = "profiling")]
+    pub fn avg_isr_micros(&self) -> i64 {
+        self.profile.avg_micros()
+    }
+
+    /// The core the interrupt handler ran on last (0 = PRO, 1 = APP), `u32::MAX` if it didn't run yet.
+    /// The gpio isr service runs on the core it was installed from, this tells whether the core
+    /// the app meant it for is the one it actually ended up on.
+    #[cfg(feature = "profiling")]
+    pub fn last_isr_core(&self) -> u32 {
+        self.profile.last_core()
+    }
+
+    /// Disable the interrupt, edges are not tracked until `enable` is called
+    pub fn disable(&mut self) -> Result<(), EspError> {
+        self.state.disable()
+    }
+
+    /// Enable the interrupt after `disable`
+    /// The pin state is read again and the trigger level re-armed to match it,
+    /// since the pin could have changed any number of times while disabled.
+    /// Fails with `ESP_ERR_INVALID_STATE` if the handle is not armed yet.
+    pub fn enable(&mut self) -> Result<(), EspError> {
+        if !self.armed {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+        }
+
+        // a sampling of a polling debouncer is dropped, the level is read again from the pin anyway
+        self.isr_off();
+        self.state.polling.store(false, Ordering::Release);
+        if let Some(poller) = &self.poller {
+            poller.resume(false);
+        }
+        self.state.handler.reopen();
+
+        self.state.enable()
+    }
+
+    /// Run `level` (`true` for high, like `current_state`) through the handler as if the interrupt had fired
+    /// and the pin settled there: debouncer, state, gate, callback, sinks and counters, all of it.
+    /// For on-target tests and demos without anyone pressing buttons.
+    ///
+    /// It bypasses the hardware interrupt and doesn't touch the pin, which is why the handle has to be
+    /// disabled (`disable`) meanwhile, so the real interrupt doesn't fight the simulated state. The callback
+    /// runs in the calling task instead of the isr. `enable` resyncs to the real pin once done.
+    /// Testing and simulation only, it is behind the `inject` feature for that reason.
+    /// Fails with `ESP_ERR_INVALID_STATE` if the handle is enabled.
+    #[cfg(feature = "inject")]
+    pub fn inject_edge(&mut self, level: bool) -> Result<(), EspError> {
+        if self.is_enabled() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+        }
+
+        let now = micros();
+        Counters::bump(&self.state.counters.edges);
+        #[cfg(feature = "stats")]
+        self.histogram.record(now);
+
+        // the trigger is armed again by `enable`, whatever the callback asked for
+        // (and a polling debouncer disabled in the middle of sampling may still be at it)
+        self.isr_off();
+        let prev_state = self.current_state();
+        self.handle_level(prev_state, level, now);
+        self.isr_on();
+
+        Ok(())
+    }
+
+    /// Same as `enable`, but first drops the events that are still waiting in `queue` from before
+    /// `disable`, so the consumer doesn't handle stale presses as if they were fresh.
+    /// The flush happens before the interrupt is enabled, so no new event can be lost to it.
+    pub fn enable_flushed<T: Copy, P: SendPolicy>(
+        &mut self,
+        queue: &IsrQueue<T, P>,
+    ) -> Result<(), EspError> {
+        queue.flush();
+        self.enable()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_enabled()
+    }
+
+    /// When the interrupt was turned on, taken right before `gpio_intr_enable` by `arm` (or `new`)
+    /// and again by every `enable`, so the edge timestamps can be lined up with the moment
+    /// the handle started listening (e.g. a host that needs to know when a capture began).
+    /// In the handle's clock like `last_edge_timestamp`, `esp_timer` time unless set with
+    /// `LeveledEdgeBuilder::clock`. `None` before arming and while disabled.
+    pub fn armed_at_micros(&self) -> Option<i64> {
+        self.state.armed_at().map(|at| self.timestamp(at))
+    }
+
+    /// Whether the debouncer rejected more edges in a row than allowed, see `LeveledEdgeBuilder::detect_unstable`
+    /// Always `false` for a handle without the detection.
+    pub fn is_input_unstable(&self) -> bool {
+        match &self.unstable {
+            Some(unstable) => unstable.is_unstable(),
+            None => false,
+        }
+    }
+
+    /// How long the interrupt was off between the last `disable` and the `enable` that followed it
+    /// (`disable_all` / `enable_all` included), to audit whether the app keeps a critical input off too long.
+    /// `None` until a disabled handle is enabled again. Edges while it was off are not seen, only the
+    /// level the pin ended at.
+    pub fn last_disabled_duration(&self) -> Option<Duration> {
+        self.state.last_disabled_duration()
+    }
+
+    /// Take the pin back after other code reconfigured it behind the handle's back (e.g. `gpio_reset_pin`),
+    /// by applying the whole configuration again: input mode, pulls, the handler and the trigger level,
+    /// and then starting over from the level the pin is at, as in `enable`. A disabled handle stays disabled.
+    /// The pulls are only known to handles made with `from_pin_num`, others have to set them again themselves.
+    ///
+    /// This is for apps that hand a pin between subsystems one at a time, reconfiguring the pin
+    /// while the handle is using it is still not supported, edges in that window are lost or wrong.
+    /// Fails with `ESP_ERR_INVALID_STATE` if the handle is not armed yet.
+    pub fn reassert(&mut self) -> Result<(), EspError> {
+        if !self.armed {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE).unwrap());
+        }
+
+        let pin = self.gpio.pin();
+
+        self.isr_off();
+        esp!(unsafe { gpio_set_direction(pin, gpio_mode_t_GPIO_MODE_INPUT) })?;
+        if let Some(pull) = self.pull {
+            esp!(unsafe { gpio_set_pull_mode(pin, pull.into()) })?;
+            // same as in `from_pin_num`, let the pull charge the line before reading it
+            unsafe { esp_rom_delay_us(10) };
+        }
+
+        esp!(unsafe {
+            esp_idf_sys::gpio_isr_handler_add(
+                pin,
+                Some(Self::irq_handler),
+                self as *mut Self as *mut _,
+            )
+        })?;
+
+        match self.is_enabled() {
+            true => self.resync_and_enable(),
+            false => {
+                esp!(unsafe { gpio_set_intr_type(pin, self.state.next_interrupt().into()) })?;
+                self.isr_on();
+                Ok(())
+            }
+        }
+    }
+
+    /// Switch the pull of the pin at runtime, e.g. between up and down to tell accessories apart.
+    /// Changing the pull changes the level the line idles at, so the handle starts over from the level
+    /// the pin settles at with the new pull, as in `enable`, and arms the interrupt for the opposite one.
+    /// The level change the pull itself causes is not an edge and is not delivered.
+    /// A disabled handle stays disabled, an unarmed one only gets the pull (it reads the pin when armed).
+    pub fn set_pull(&mut self, pull: PullMode) -> Result<(), EspError> {
+        let pin = self.gpio.pin();
+
+        self.isr_off();
+        // e.g. GPIO34-39 of the esp32 have no pulls, nothing changed then
+        if let Err(err) = esp!(unsafe { gpio_set_pull_mode(pin, pull.into()) }) {
+            self.isr_on();
+            return Err(err);
+        }
+        self.pull = Some(pull);
+        // same as in `from_pin_num`, let the pull charge the line before reading it
+        unsafe { esp_rom_delay_us(10) };
+
+        if !self.armed {
+            return Ok(());
+        }
+
+        match self.is_enabled() {
+            true => self.resync_and_enable(),
+            // still with the interrupt off from above
+            false => {
+                let resynced = self
+                    .state
+                    .resync()
+                    .map(|level| self.last_edge.write((level, micros())));
+                self.isr_on();
+                resynced
+            }
+        }
+    }
+
+    /// Change what the handle does with the missed edges it finds, see `MissedEdgePolicy`
+    pub fn set_missed_edge_policy(&mut self, policy: MissedEdgePolicy) {
+        // read by the isr, keep it out while changing it
+        self.isr_off();
+        self.missed_edge_policy = policy;
+        self.isr_on();
+    }
+
+    /// Compare the tracked state to the actual level of the pin and fix it if they disagree,
+    /// meaning an edge was missed and the interrupt is armed for a level the pin is already at.
+    /// Meant to be called periodically from a low priority supervisor task for critical inputs,
+    /// returns whether a correction was made. See `MissedEdgePolicy`.
+    pub fn verify_and_correct(&mut self) -> Result<bool, EspError> {
+        // keep the isr out while the state is compared and fixed
+        self.isr_off();
+        let corrected = self.correct_missed_edge();
+        self.isr_on();
+
+        corrected
+    }
+
+    /// `verify_and_correct` with the isr already kept out, by the caller or a group guard
+    pub(crate) fn correct_missed_edge(&mut self) -> Result<bool, EspError> {
+        let pin = self.gpio.pin();
+        let level = unsafe { gpio_get_level(pin) } != 0;
+
+        let level = match missed_edge(self.current_state(), level, self.state.is_polling()) {
+            Some(level) => level,
+            None => return Ok(false),
+        };
+
+        self.state.pin_state.store(level, Ordering::Release);
+        esp!(unsafe { gpio_set_intr_type(pin, self.state.next_interrupt().into()) })?;
+
+        // a correction is about getting back in sync, an `EdgeAction::NextLevel` is ignored here
+        match self.missed_edge_policy {
+            MissedEdgePolicy::FireCallback => {
+                let active = self.active.is_active(level);
+                self.accept_edge(level, active, micros(), EdgeContext::Forced);
             }
+            MissedEdgePolicy::SilentResync => self.last_edge.write((level, micros())),
         }
 
+        Ok(true)
+    }
+
+    /// Deliver the level the pin is at as an `EdgeContext::InitialState` edge, from the calling task
+    fn emit_current_state(&mut self) {
+        self.isr_off();
+
+        let level = self.current_state();
+        // NOTE: a starting state, not an edge: not counted and never arms anything but the opposite level
+        self.deliver(
+            level,
+            self.active.is_active(level),
+            micros(),
+            EdgeContext::InitialState,
+        );
+
+        self.isr_on();
+    }
+
+    ///Install the interrupt handler on the pin supplied
+    fn install_isr(&mut self) -> Result<(), EspError> {
+        self.isr_off();
+
+        install_service(&self.isr_config)?;
+        self.holds_service = true;
+
         esp!(unsafe {
             esp_idf_sys::gpio_isr_handler_add(
                 self.gpio.pin(),
@@ -103,7 +960,64 @@ where
             )
         })?;
 
-        Ok(())
+        // the line may still be settling, let it and start from where it ended up
+        if !self.arming_delay.is_zero() {
+            std::thread::sleep(self.arming_delay);
+        }
+
+        self.resync_and_enable()
+    }
+
+    /// Start over from the level the pin is at now, as if it was just created, and enable the interrupt
+    /// The interrupt has to be off: it is only enabled once everything else is written,
+    /// as `last_edge` has the isr as its only writer and readers that spin over a write in progress.
+    fn resync_and_enable(&mut self) -> Result<(), EspError> {
+        self.debouncer.reset();
+        // a sampling cut short by `isr_off` is dropped, this starts over from the pin anyway
+        self.state.polling.store(false, Ordering::Release);
+        let level = self.state.resync()?;
+        self.last_edge.write((level, micros()));
+        if let Some(output) = &self.output {
+            output.set(level);
+        }
+
+        if let Some(poller) = &self.poller {
+            poller.resume(false);
+        }
+        self.state.handler.reopen();
+        self.state.enable_armed()
+    }
+
+    /// Keep the isr and the sampling of a polling debouncer out, for changing what they use.
+    /// Disabling the interrupt alone doesn't stop an isr already running on the other core,
+    /// or a sample running in the esp_timer task, both are waited for. Until `isr_on` (or
+    /// `resync_and_enable` when starting over) the isr doesn't run, even if something enables
+    /// the interrupt meanwhile, e.g. a deferred task releasing it.
+    ///
+    /// Not a `critical_section::with`: that only keeps the isr out if the isr takes it as well,
+    /// for all of its run, and the crate's one lock would then be held over the user's callback.
+    pub(crate) fn isr_off(&self) {
+        unsafe { gpio_intr_disable(self.gpio.pin()) };
+        // an isr is short, and can't be running on this core while this task is
+        while !self.state.handler.try_close() {
+            spin_loop();
+        }
+        if let Some(poller) = &self.poller {
+            poller.pause();
+        }
+    }
+
+    /// Undo `isr_off`: a sampling it interrupted goes on, otherwise the interrupt is enabled again,
+    /// unless the handle is disabled or held off for a deferred task (see `HandleState::may_enable`)
+    pub(crate) fn isr_on(&self) {
+        if let Some(poller) = &self.poller {
+            poller.resume(self.state.is_polling());
+        }
+        self.state.handler.reopen();
+
+        if self.state.may_enable() {
+            unsafe { gpio_intr_enable(self.gpio.pin()) };
+        }
     }
 
     /// This is the real interrupt handler being run on interrupt,
@@ -114,34 +1028,599 @@ where
     unsafe extern "C" fn irq_handler(this: *mut esp_idf_sys::c_types::c_void) {
         let this: &mut LeveledEdge<Debouncer, Func> = &mut *(this as *mut _);
 
-        // disable the interrupt, maybe not really needed..
-        esp_idf_sys::gpio_intr_disable(this.gpio.pin());
+        // a task is changing the handle (`isr_off`), stay out and leave it off, `isr_on` enables it
+        if !this.state.handler.enter() {
+            gpio_intr_disable(this.gpio.pin());
+            return;
+        }
+
+        Self::handle_interrupt(this);
+        this.state.handler.leave();
+    }
+
+    /// The interrupt handler, once `irq_handler` made sure no task is in the middle of changing the handle
+    #[inline(always)]
+    unsafe fn handle_interrupt(this: &mut Self) {
+        // the status of a level interrupt is only cleared after this returns, and re-asserted right away
+        // while the trigger matches the pin. The paths that return without re-arming (the poller,
+        // backpressure, a disable meanwhile) need it off, see `LeveledEdgeBuilder::disable_during_isr`
+        if this.disable_during_isr {
+            gpio_intr_disable(this.gpio.pin());
+        }
+        let now = micros();
+
+        if let Some(sampled_pin) = &this.sampled_pin {
+            sampled_pin.sample();
+        }
+        Counters::bump(&this.state.counters.edges);
+
+        #[cfg(feature = "stats")]
+        this.histogram.record(now);
+
+        // a polling debouncer takes it from here, with the interrupt off until it's done
+        if let Some(poller) = &mut this.poller {
+            this.state.polling.store(true, Ordering::Release);
+            poller.start(!this.state.pin_state.load(Ordering::Relaxed));
+            return;
+        }
+
+        //toggle the pin state, then let it settle in case more edges came while we got here
+        let prev_state = this.state.pin_state.load(Ordering::Relaxed);
+        let pin_state = settle(this.gpio.pin(), !prev_state);
+        let action = this.handle_level(prev_state, pin_state, now);
+
+        //toggle the interrupt type (or whatever the callback asked for)
+        this.toggle_interrupt_trigger(action);
+
+        #[cfg(feature = "profiling")]
+        this.profile.record(now, micros());
 
-        //toggle the pin state
-        this.pin_state = !this.pin_state;
+        // unless the handle was disabled meanwhile, or a deferred task is to enable it
+        if this.disable_during_isr && this.state.may_enable() {
+            gpio_intr_enable(this.gpio.pin());
+        }
+    }
+
+    /// What the handler does with the level the pin settled at, up to re-arming the trigger
+    /// Shared by the interrupt handler and `inject_edge`, returns what the callback asked for.
+    #[inline(always)]
+    fn handle_level(&mut self, prev_state: bool, pin_state: bool, now: i64) -> EdgeAction {
+        self.state.pin_state.store(pin_state, Ordering::Release);
 
         //debounce the pin, call callback if debounced
-        if this.debouncer.is_isr_valid() {
-            (*this.callback)(this.pin_state);
+        //if the pin came back to where it was there is no edge to report
+        let active = self.active.is_active(pin_state);
+        let mut action = EdgeAction::Continue;
+        let accepted = pin_state != prev_state && self.debouncer.is_edge_valid(active);
+        if let Some(settling) = &mut self.settling {
+            match accepted {
+                true => settling.edge(now),
+                false => settling.bounce(now),
+            }
+        }
+        if let Some(unstable) = &mut self.unstable {
+            unstable.edge(accepted, now);
+        }
+        #[cfg(feature = "stats")]
+        self.histogram.decided(now, accepted);
+
+        if pin_state != prev_state {
+            if accepted {
+                action = self.accept_edge(pin_state, active, now, EdgeContext::Normal);
+            } else {
+                Counters::bump(&self.state.counters.rejected);
+
+                #[cfg(feature = "trace")]
+                self.trace.record(TraceEntry {
+                    timestamp: now,
+                    level: pin_state,
+                    // filled in when read back by `trace`
+                    label: None,
+                    accepted: false,
+                    missed_recovery: false,
+                });
+            }
+        }
+
+        action
+    }
+
+    /// The sampling of a polling debouncer, from the esp_timer task while the interrupt is off.
+    /// Once the pin is stable it is handled like an edge in `irq_handler`, and the interrupt is armed again.
+    /// The control methods keep it out with `isr_off` like the isr, it only runs between an edge and
+    /// the interrupt being enabled again.
+    unsafe fn poll_sample(this: usize) {
+        let this = &mut *(this as *mut Self);
+        let level = gpio_get_level(this.gpio.pin()) != 0;
+        let now = micros();
+
+        let pin_state = match this.poller.as_mut().and_then(|poller| poller.sample(level)) {
+            Some(pin_state) => pin_state,
+            None => return,
+        };
+
+        // bounced back to where it was or not, from here on it's the same as in the isr
+        let prev_state = this.state.pin_state.load(Ordering::Relaxed);
+        let action = this.handle_level(prev_state, pin_state, now);
+        this.toggle_interrupt_trigger(action);
+
+        this.state.polling.store(false, Ordering::Release);
+        if this.state.may_enable() {
+            gpio_intr_enable(this.gpio.pin());
+        }
+    }
+
+    /// Deliver an edge the debouncer accepted, unless muted or the gate drops it
+    /// context: `Normal` for an edge of the isr, made `MissedEdgeRecovery` here if it is one
+    #[inline(always)]
+    fn accept_edge(
+        &mut self,
+        pin_state: bool,
+        active: bool,
+        now: i64,
+        context: EdgeContext,
+    ) -> EdgeAction {
+        Counters::bump(&self.state.counters.accepted);
+        let (last_level, _) = self.last_edge.read();
+        let missed_recovery = pin_state == last_level;
+        if missed_recovery {
+            Counters::bump(&self.state.counters.missed_edge_recoveries);
+        }
+        self.last_edge.write((pin_state, now));
+
+        let waiter = self.waiter.load(Ordering::Acquire);
+        if !waiter.is_null() {
+            notify_from_isr(waiter, 1);
+        }
+
+        #[cfg(feature = "trace")]
+        self.trace.record(TraceEntry {
+            timestamp: now,
+            level: pin_state,
+            label: None,
+            accepted: true,
+            missed_recovery,
+        });
+
+        if missed_recovery && self.missed_edge_policy == MissedEdgePolicy::SilentResync {
+            return EdgeAction::Continue;
+        }
+
+        let context = match (context, missed_recovery) {
+            (EdgeContext::Normal, true) => EdgeContext::MissedEdgeRecovery,
+            (context, _) => context,
+        };
+
+        self.deliver(pin_state, active, now, context)
+    }
+
+    /// Hand an edge to the gate, the output, the callback and the sinks
+    fn deliver(
+        &mut self,
+        pin_state: bool,
+        active: bool,
+        now: i64,
+        context: EdgeContext,
+    ) -> EdgeAction {
+        let allowed = match &mut self.gate {
+            Some(gate) => gate.allow(active, now),
+            None => true,
+        };
+
+        // orders nothing else, see `EdgeFlags`
+        if !allowed || self.muted.load(Ordering::Relaxed) {
+            Counters::bump(&self.state.counters.dropped);
+            return EdgeAction::Continue;
+        }
+
+        if let Some(output) = &self.output {
+            output.set(pin_state);
+        }
+
+        self.event
+            .store(EVENT_INACTIVE + active as u8, Ordering::Release);
+
+        let action = match &mut self.callback {
+            Some(callback) => Self::call(
+                callback,
+                active,
+                self.user_data,
+                context,
+                &self.state,
+                &self.state.counters,
+            ),
+            None => EdgeAction::Continue,
+        };
+
+        if !self.sinks.is_empty() {
+            let event = EdgeEvent {
+                pin: self.state.pin,
+                label: self.state.label,
+                user_data: self.user_data,
+                active,
+                timestamp: now,
+            };
+            for sink in &mut self.sinks {
+                sink.deliver(&event);
+            }
         }
 
-        //toggle the interrupt type
-        this.toggle_interrupt_trigger();
-        esp_idf_sys::gpio_intr_enable(this.gpio.pin());
+        action
+    }
+
+    #[cfg(not(feature = "catch_panics"))]
+    #[inline(always)]
+    fn call(
+        callback: &mut Func,
+        active: bool,
+        user_data: u32,
+        context: EdgeContext,
+        _state: &HandleState,
+        _counters: &Counters,
+    ) -> EdgeAction {
+        callback.on_edge_with_context(active, user_data, context)
+    }
+
+    /// Run the callback, catching a panic in it so it takes down its own handle and not the device.
+    /// The handle is disabled with the interrupt still off (it isn't enabled again on the way out)
+    /// and the panic is counted, see `LeveledEdgeTelemetry::callback_panics`.
+    /// Only works with `panic = "unwind"`, with `panic = "abort"` nothing is caught and it aborts as before.
+    #[cfg(feature = "catch_panics")]
+    #[inline(always)]
+    fn call(
+        callback: &mut Func,
+        active: bool,
+        user_data: u32,
+        context: EdgeContext,
+        state: &HandleState,
+        counters: &Counters,
+    ) -> EdgeAction {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            callback.on_edge_with_context(active, user_data, context)
+        }));
+
+        match result {
+            Ok(action) => action,
+            Err(_) => {
+                Counters::bump(&counters.callback_panics);
+                state.enabled.store(false, Ordering::Release);
+                EdgeAction::Continue
+            }
+        }
     }
 
     ///toggle the interrupt trigger between high and low level
-    fn toggle_interrupt_trigger(&mut self) {
-        let next_intr = match self.pin_state {
-            true => InterruptType::LowLevel,
-            false => InterruptType::HighLevel,
+    fn toggle_interrupt_trigger(&mut self, action: EdgeAction) {
+        let next = match action {
+            EdgeAction::Continue => self.state.next_interrupt(),
+            EdgeAction::NextLevel(next) => next,
         };
 
         unsafe {
-            match gpio_set_intr_type(self.gpio.pin(), next_intr.into()) {
+            match gpio_set_intr_type(self.gpio.pin(), next.into()) {
                 ESP_OK => {}
                 err => panic!("gpio_set_intr_type failed: {:?}", err),
             }
         }
     }
 }
+
+impl<Debouncer, Func> Drop for LeveledEdge<Debouncer, Func>
+where
+    Debouncer: Debounce,
+    Func: EdgeCallback,
+{
+    /// Remove the interrupt handler before anything it uses (callback, sinks, deferred task...) is dropped
+    fn drop(&mut self) {
+        if self.detached {
+            return;
+        }
+
+        self.tear_down();
+        unsafe {
+            ManuallyDrop::drop(&mut self.gpio);
+            ManuallyDrop::drop(&mut self.debouncer);
+        }
+    }
+}
+
+impl<Debouncer, Func> LeveledEdge<Debouncer, Func>
+where
+    Debouncer: Debounce,
+    Func: EdgeCallback,
+{
+    /// Take the handle apart to reuse the pin, returns the pin, the debouncer (with whatever it learned)
+    /// and the callback (`None` after `unsubscribe` or for `state_only` handles), as `reconfigure` does.
+    /// Everything else is dropped. The interrupt handler is removed and the pin's interrupt type
+    /// cleared before anything is moved out, so no isr can run on them anymore.
+    // handles only ever exist boxed, taking the box is what lets `handle.detach()` be called on them
+    #[allow(clippy::boxed_local)]
+    pub fn detach(mut self: Box<Self>) -> (GpioPin<Input>, Debouncer, Option<Func>) {
+        self.tear_down();
+        unsafe {
+            gpio_set_intr_type(
+                self.gpio.pin(),
+                esp_idf_sys::gpio_int_type_t_GPIO_INTR_DISABLE,
+            )
+        };
+
+        self.detached = true;
+        let gpio = unsafe { ManuallyDrop::take(&mut self.gpio) };
+        let debouncer = unsafe { ManuallyDrop::take(&mut self.debouncer) };
+
+        (gpio, debouncer, self.callback.take())
+    }
+
+    /// What `Drop` and `detach` do before letting go of the fields
+    fn tear_down(&mut self) {
+        // first, a sample running right now still uses all of the handle
+        if let Some(poller) = &self.poller {
+            poller.pause();
+        }
+
+        #[cfg(feature = "registry")]
+        registry::unregister(&self.state);
+
+        if let Some(hold_off) = &self.state.hold_off {
+            hold_off.close();
+        }
+
+        unsafe {
+            gpio_intr_disable(self.gpio.pin());
+            esp_idf_sys::gpio_isr_handler_remove(self.gpio.pin());
+        }
+
+        if self.holds_service {
+            release_service(&self.isr_config);
+        }
+    }
+}
+
+impl<Func> LeveledEdge<ConfigDebounce, Func>
+where
+    Func: EdgeCallback,
+{
+    /// Create a handle from its description, e.g. loaded from NVS at boot, set up as in `from_pin_num`
+    /// The config is checked before touching the pin, see `ConfigError` for what it is checked for.
+    ///
+    /// # Safety
+    /// Same as `from_pin_num`, for the gpio of `config`
+    pub unsafe fn from_config(
+        config: &LeveledEdgeConfig,
+        callback: Func,
+    ) -> Result<Box<Self>, ConfigError> {
+        if check_interrupt_capable(config.gpio).is_err() {
+            return Err(ConfigError::InvalidPin(config.gpio));
+        }
+        if config.debounce != DebounceKind::None && config.debounce_ms == 0 {
+            return Err(ConfigError::ZeroDebounceTime);
+        }
+
+        let debounce_time = Duration::from_millis(config.debounce_ms as u64);
+        let debouncer = ConfigDebounce::new(config.debounce, debounce_time);
+        let pin_config = PinConfig {
+            pull: config.pull,
+            active: config.active,
+        };
+
+        let mut builder = Self::pin_num_builder(config.gpio, pin_config, debouncer, callback)?;
+        if config.edge_filter != EdgeFilter::Both {
+            builder = builder.gate(config.edge_filter);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+impl<Debouncer, F> LeveledEdge<Debouncer, Tagged<F>>
+where
+    Debouncer: Debounce,
+    F: FnMut(u32, bool),
+{
+    /// Same as `new`, but the callback also gets `user_data`, for one callback shared by many handles
+    pub fn new_tagged(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        user_data: u32,
+        callback: F,
+    ) -> Result<Box<Self>, EspError> {
+        Self::builder(gpio, debouncer, Tagged(callback))
+            .user_data(user_data)
+            .build()
+    }
+
+    /// A handle for every pin of `configs`, set up as in `from_pin_num`, for a keypad or a bank of
+    /// DIP switches that would otherwise be built one handle at a time. Handle `i` gets `i` as its
+    /// user data, so the callback (cloned for every handle) knows which pin changed.
+    /// debouncer: Called once per pin for its debouncer, the same config for all of them
+    /// If any pin fails, the handles installed before it are dropped (uninstalled) before returning the error.
+    ///
+    /// # Safety
+    /// Same as `from_pin_num`, for every pin of `configs`
+    pub unsafe fn new_bank(
+        configs: &[(i32, PinConfig)],
+        mut debouncer: impl FnMut() -> Debouncer,
+        callback: F,
+    ) -> Result<Vec<Box<Self>>, EspError>
+    where
+        F: Clone,
+    {
+        configs
+            .iter()
+            .enumerate()
+            .map(|(index, &(gpio_num, config))| {
+                let callback = Tagged(callback.clone());
+                Self::pin_num_builder(gpio_num, config, debouncer(), callback)?
+                    .user_data(index as u32)
+                    .build()
+            })
+            .collect()
+    }
+}
+
+impl<Debouncer> LeveledEdge<Debouncer, RawCallback>
+where
+    Debouncer: Debounce,
+{
+    /// Same as `new`, but the callback is `func`, called with the active state and `context`
+    ///
+    /// # Safety
+    /// `context` is handed to `func` from the interrupt handler as is, it must stay valid
+    /// for as long as the handle lives and be safe to use from the isr.
+    pub unsafe fn new_raw(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        func: extern "C" fn(bool, *mut esp_idf_sys::c_types::c_void),
+        context: *mut esp_idf_sys::c_types::c_void,
+    ) -> Result<Box<Self>, EspError> {
+        Self::new(gpio, debouncer, RawCallback { func, context })
+    }
+}
+
+/// The pin of the BOOT button of the dev boards of the current chip
+#[cfg(not(esp32c3))]
+pub type BootPin = esp_idf_hal::gpio::Gpio0<Input>;
+#[cfg(esp32c3)]
+pub type BootPin = esp_idf_hal::gpio::Gpio9<Input>;
+
+impl<Func> LeveledEdge<ClassicDebounce, Func>
+where
+    Func: EdgeCallback,
+{
+    /// The debounce window of `boot_button`, plenty for the tactile switches of the dev boards
+    pub const BOOT_BUTTON_DEBOUNCE: Duration = Duration::from_millis(20);
+
+    /// The BOOT button of a dev board, ready to go: the internal pull up on, active low
+    /// (the callback gets `true` on press) and a `ClassicDebounce` of `BOOT_BUTTON_DEBOUNCE`.
+    ///
+    /// The button is on a strapping pin (GPIO0, GPIO9 on the esp32c3): held down during reset it puts the chip
+    /// in download mode, and anything else wired to that pin must not pull it low at boot either.
+    /// On the esp32 GPIO0 is also a clock output option and is used by some ethernet PHYs, don't count
+    /// on it on boards that do that.
+    ///
+    /// # Example - print the button presses
+    /// ```
+    /// let _button = LeveledEdge::boot_button(pins.gpio0.into_input().unwrap(), |pressed| {
+    ///     println!("BOOT {}", pressed)
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn boot_button(pin: BootPin, callback: Func) -> Result<Box<Self>, EspError> {
+        let gpio = pin.degrade();
+        esp!(unsafe { gpio_set_pull_mode(gpio.pin(), PullMode::Up.into()) })?;
+        // same as in `from_pin_num`, let the pull charge the line before reading it
+        unsafe { esp_rom_delay_us(10) };
+
+        Self::builder(
+            gpio,
+            ClassicDebounce::new(Self::BOOT_BUTTON_DEBOUNCE),
+            callback,
+        )
+        .active(Level::Low)
+        .pull(PullMode::Up)
+        .build()
+    }
+}
+
+impl<Debouncer> LeveledEdge<Debouncer, EdgeCounter>
+where
+    Debouncer: Debounce,
+{
+    /// Count the accepted edges that pass `edge` into `counter`, for "how many times was it pressed"
+    /// without a closure touching the app's state. The handler only does a `fetch_add` on it,
+    /// the app reads (or swaps to 0) the counter whenever it likes.
+    pub fn count_into(
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        counter: &'static AtomicU32,
+        edge: EdgeFilter,
+    ) -> Result<Box<Self>, EspError> {
+        Self::new(gpio, debouncer, EdgeCounter { counter, edge })
+    }
+}
+
+impl<Debouncer> LeveledEdge<Debouncer, NoCallback>
+where
+    Debouncer: Debounce,
+{
+    /// Make `output` mirror the debounced level of `input`, no callback needed.
+    /// invert: drive the output low while the input is high and the other way around
+    ///
+    /// # Example - light the led on pin 18 while the button on pin 4 is pressed (active low)
+    /// ```
+    /// let _led = LeveledEdge::follow(
+    ///     pins.gpio4.into_input().unwrap().degrade(),
+    ///     ClassicDebounce::new(Duration::from_millis(20)),
+    ///     pins.gpio18.into_output().unwrap().degrade(),
+    ///     true,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn follow(
+        input: GpioPin<Input>,
+        debouncer: Debouncer,
+        output: GpioPin<Output>,
+        invert: bool,
+    ) -> Result<Box<Self>, EspError> {
+        LeveledEdgeBuilder::new(input, debouncer, no_callback as NoCallback)
+            .output(output, invert)
+            .build()
+    }
+    /// A handle that only keeps track of the pin, the interrupt handler calls nothing on an edge.
+    /// For a supervisor that polls `current_state`, `state_snapshot` or `time_in_state_micros`
+    /// (or `take_event`), without an empty closure to pass. Edges are only seen by polling,
+    /// nothing tells the app when one comes in.
+    pub fn state_only(gpio: GpioPin<Input>, debouncer: Debouncer) -> Result<Box<Self>, EspError> {
+        let mut handle =
+            LeveledEdgeBuilder::new(gpio, debouncer, no_callback as NoCallback).prepare()?;
+        // not armed yet, no isr to race
+        handle.callback = None;
+        handle.arm()?;
+
+        Ok(handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The reads of a pin, then stuck at the last one
+    fn pin_reads(levels: &[bool]) -> impl FnMut() -> bool + '_ {
+        let mut reads = levels.iter();
+        let last = *levels.last().unwrap();
+        move || *reads.next().unwrap_or(&last)
+    }
+
+    #[test]
+    fn missed_edges() {
+        assert_eq!(missed_edge(false, true, false), Some(true));
+        assert_eq!(missed_edge(true, false, false), Some(false));
+        assert_eq!(missed_edge(true, true, false), None);
+        // left to the sampling
+        assert_eq!(missed_edge(false, true, true), None);
+    }
+
+    #[test]
+    fn settle_coalesces_chatter() {
+        // went high, chattered while the isr got there, ended up low
+        let reads = [false, true, false, true, false, false, false];
+        assert!(!settle_reads(true, pin_reads(&reads)));
+
+        let reads = [true, false, true, true, true];
+        assert!(settle_reads(true, pin_reads(&reads)));
+    }
+
+    #[test]
+    fn settle_is_bounded() {
+        let mut count = 0;
+        let oscillating = || {
+            count += 1;
+            count % 2 == 0
+        };
+
+        // gives up with the last level read, the level interrupt fires again for the rest
+        assert!(settle_reads(false, oscillating));
+        assert_eq!(count, SETTLE_MAX_READS);
+    }
+}
@@ -1,9 +1,83 @@
 
+mod builder;
+mod busy;
+mod callback;
+mod capabilities;
+mod clock;
+mod config;
+#[cfg(feature = "critical_section_impl")]
+mod critical;
 mod debounce;
+mod deferred;
+mod edge;
+mod encoder;
+mod flags;
+mod follower;
+mod frequency;
+mod gate;
+mod group;
+mod input;
+mod key;
+mod latch;
 mod leveled_edge;
+#[cfg(all(feature = "pcnt", not(esp32c3)))]
+mod pcnt;
+mod pins;
+mod poll;
+#[cfg(feature = "profiling")]
+mod profile;
+mod pulse;
+mod queue;
+#[cfg(feature = "registry")]
+mod registry;
+mod seqlock;
+mod service;
+mod settling;
+mod shared_line;
+mod sink;
+mod state;
+#[cfg(feature = "stats")]
+mod stats;
+mod telemetry;
+mod timer;
+#[cfg(feature = "trace")]
+mod trace;
+mod unstable;
 
+pub use builder::*;
+pub use callback::*;
+pub use capabilities::{capabilities, Capabilities};
+pub use clock::{Clock, SystemClock};
+pub use config::{ConfigDebounce, ConfigError, DebounceKind, LeveledEdgeConfig};
 pub use debounce::*;
+pub use deferred::DeferredCallback;
+pub use edge::*;
+pub use encoder::*;
+pub use flags::EdgeFlags;
+pub use frequency::FrequencyCounter;
+pub use gate::*;
+pub use group::*;
+pub use input::{InputEvent, InputSource};
+pub use key::{AsKey, KeyEvent};
+pub use latch::*;
 pub use leveled_edge::*;
+#[cfg(all(feature = "pcnt", not(esp32c3)))]
+pub use pcnt::PcntEncoder;
+pub use pins::{InterruptCapable, PinConfig, PullMode};
+pub use poll::*;
+pub use pulse::PulseMeter;
+pub use queue::*;
+#[cfg(feature = "registry")]
+pub use registry::{crash_summary, disable_all, enable_all, InputSummary};
+pub use service::{service_refcount, IsrConfig};
+pub use shared_line::*;
+pub use sink::{DeferredSink, EdgeEvent, Sink};
+#[cfg(feature = "stats")]
+pub use stats::{HISTOGRAM_BUCKETS, HISTOGRAM_LIMITS_MICROS, RECOMMEND_MIN_EDGES};
+pub use telemetry::LeveledEdgeTelemetry;
+pub use timer::{stop_all_timers, timer_count};
+#[cfg(feature = "trace")]
+pub use trace::{TraceEntry, DEFAULT_TRACE_DEPTH};
 
 
 // A simple crate that provides safer any edge interrupts for esp32, using alternating level interrupts (with debouncing)
@@ -0,0 +1,181 @@
+use crate::debounce::Debounce;
+use crate::encoder::Encoder;
+use crate::leveled_edge::LeveledEdge;
+use esp_idf_hal::gpio::{GpioPin, Input, Pin};
+use esp_idf_sys::{
+    c_types::c_void, esp, pcnt_config_t, pcnt_unit_t, EspError, ESP_ERR_INVALID_STATE, ESP_OK,
+};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+type SwitchCallback = Box<dyn FnMut(bool)>;
+
+/// What the PCNT interrupt handler needs, boxed so its address stays put
+struct UnitState {
+    unit: pcnt_unit_t,
+    overflow: AtomicI32,
+}
+
+/// A quadrature encoder counted by the PCNT peripheral, no interrupt per step
+///
+/// `clk` is the pulse input and `dt` the control input of a single channel, so every edge of `clk`
+/// counts one step and the level of `dt` decides the direction (same as `RotaryEncoder`).
+/// The hardware counter is only 16 bit, it is reset on reaching +-`COUNTER_LIMIT`
+/// and the overflows are accumulated in an interrupt handler.
+///
+/// The push switch of the encoder (if any) is not counted in hardware,
+/// it is handled by a regular `LeveledEdge`, hence the `Debouncer` parameter.
+/// Without a switch it can be anything, e.g. `PcntEncoder::<NoDebounce>::new(...)`
+pub struct PcntEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    state: Box<UnitState>,
+    last: i64,
+    _clk: GpioPin<Input>,
+    _dt: GpioPin<Input>,
+    _switch: Option<Box<LeveledEdge<Debouncer, SwitchCallback>>>,
+}
+
+impl<Debouncer> PcntEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    const COUNTER_LIMIT: i16 = 10000;
+    /// Pulses shorter than this many APB cycles (80MHz) are ignored, 1023 at most
+    const FILTER_CYCLES: u16 = 1000;
+
+    /// Create a new instance of `PcntEncoder`
+    /// unit: The PCNT unit to use, it is owned by the encoder until dropped
+    pub fn new(
+        unit: pcnt_unit_t,
+        clk: GpioPin<Input>,
+        dt: GpioPin<Input>,
+    ) -> Result<Self, EspError> {
+        let config = pcnt_config_t {
+            pulse_gpio_num: clk.pin(),
+            ctrl_gpio_num: dt.pin(),
+            lctrl_mode: esp_idf_sys::pcnt_ctrl_mode_t_PCNT_MODE_REVERSE,
+            hctrl_mode: esp_idf_sys::pcnt_ctrl_mode_t_PCNT_MODE_KEEP,
+            pos_mode: esp_idf_sys::pcnt_count_mode_t_PCNT_COUNT_DEC,
+            neg_mode: esp_idf_sys::pcnt_count_mode_t_PCNT_COUNT_INC,
+            counter_h_lim: Self::COUNTER_LIMIT,
+            counter_l_lim: -Self::COUNTER_LIMIT,
+            unit,
+            channel: esp_idf_sys::pcnt_channel_t_PCNT_CHANNEL_0,
+        };
+
+        let encoder = PcntEncoder {
+            state: Box::new(UnitState {
+                unit,
+                overflow: AtomicI32::new(0),
+            }),
+            last: 0,
+            _clk: clk,
+            _dt: dt,
+            _switch: None,
+        };
+
+        unsafe {
+            esp!(esp_idf_sys::pcnt_unit_config(&config))?;
+            esp!(esp_idf_sys::pcnt_set_filter_value(
+                unit,
+                Self::FILTER_CYCLES
+            ))?;
+            esp!(esp_idf_sys::pcnt_filter_enable(unit))?;
+            esp!(esp_idf_sys::pcnt_event_enable(
+                unit,
+                esp_idf_sys::pcnt_evt_type_t_PCNT_EVT_H_LIM
+            ))?;
+            esp!(esp_idf_sys::pcnt_event_enable(
+                unit,
+                esp_idf_sys::pcnt_evt_type_t_PCNT_EVT_L_LIM
+            ))?;
+            esp!(esp_idf_sys::pcnt_counter_pause(unit))?;
+            esp!(esp_idf_sys::pcnt_counter_clear(unit))?;
+
+            match esp_idf_sys::pcnt_isr_service_install(0) {
+                ESP_OK | ESP_ERR_INVALID_STATE => {}
+                err => return Err(EspError::from(err).unwrap()),
+            }
+
+            esp!(esp_idf_sys::pcnt_isr_handler_add(
+                unit,
+                Some(Self::irq_handler),
+                encoder.state.as_ref() as *const _ as *mut c_void,
+            ))?;
+            esp!(esp_idf_sys::pcnt_counter_resume(unit))?;
+        }
+
+        Ok(encoder)
+    }
+
+    /// Attach the push switch of the encoder, handled by a `LeveledEdge`
+    /// callback: Called from the interrupt handler with the debounced switch level
+    pub fn with_switch(
+        mut self,
+        gpio: GpioPin<Input>,
+        debouncer: Debouncer,
+        callback: impl FnMut(bool) + 'static,
+    ) -> Result<Self, EspError> {
+        let callback: SwitchCallback = Box::new(callback);
+        self._switch = Some(LeveledEdge::new(gpio, debouncer, callback)?);
+
+        Ok(self)
+    }
+
+    /// The counter was reset after reaching one of its limits, keep what it counted so far
+    #[link_section = ".iram1.pcnt_encoder"]
+    unsafe extern "C" fn irq_handler(arg: *mut c_void) {
+        let state = &*(arg as *const UnitState);
+        let mut status = 0;
+        esp_idf_sys::pcnt_get_event_status(state.unit, &mut status);
+
+        if status & esp_idf_sys::pcnt_evt_type_t_PCNT_EVT_H_LIM != 0 {
+            state
+                .overflow
+                .fetch_add(Self::COUNTER_LIMIT as i32, Ordering::AcqRel);
+        } else if status & esp_idf_sys::pcnt_evt_type_t_PCNT_EVT_L_LIM != 0 {
+            state
+                .overflow
+                .fetch_sub(Self::COUNTER_LIMIT as i32, Ordering::AcqRel);
+        }
+    }
+}
+
+impl<Debouncer> Encoder for PcntEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    fn position(&self) -> i64 {
+        // the counter may wrap between the two reads, read again until the overflow is stable
+        loop {
+            let overflow = self.state.overflow.load(Ordering::Acquire);
+            let mut count = 0;
+            unsafe { esp_idf_sys::pcnt_get_counter_value(self.state.unit, &mut count) };
+
+            if overflow == self.state.overflow.load(Ordering::Acquire) {
+                return overflow as i64 + count as i64;
+            }
+        }
+    }
+
+    fn take_delta(&mut self) -> i64 {
+        let position = self.position();
+        let delta = position - self.last;
+        self.last = position;
+
+        delta
+    }
+}
+
+impl<Debouncer> Drop for PcntEncoder<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    fn drop(&mut self) {
+        unsafe {
+            esp_idf_sys::pcnt_counter_pause(self.state.unit);
+            esp_idf_sys::pcnt_isr_handler_remove(self.state.unit);
+        }
+    }
+}
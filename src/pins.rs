@@ -0,0 +1,109 @@
+use crate::edge::Level;
+use esp_idf_hal::gpio::*;
+use esp_idf_sys::{gpio_num_t_GPIO_NUM_MAX, gpio_pull_mode_t, EspError, ESP_ERR_INVALID_ARG};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A marker trait for typed input pins that can be used as a leveled edge interrupt source
+///
+/// It is only implemented for the concrete gpio types of the current chip which can raise
+/// gpio interrupts and are not reserved for the SPI flash, so handing anything else to
+/// `LeveledEdge::new_typed` fails to compile instead of failing at runtime.
+pub trait InterruptCapable: Pin {
+    fn into_interrupt_pin(self) -> GpioPin<Input>;
+}
+
+macro_rules! interrupt_capable {
+    ($($pxi:ident),*) => {
+        $(
+            impl InterruptCapable for $pxi<Input> {
+                fn into_interrupt_pin(self) -> GpioPin<Input> {
+                    self.degrade()
+                }
+            }
+        )*
+    };
+}
+
+// NOTE: Gpio6 - Gpio11 are connected to the SPI flash and left out on purpose
+#[cfg(esp32)]
+interrupt_capable!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15, Gpio16, Gpio17,
+    Gpio18, Gpio19, Gpio21, Gpio22, Gpio23, Gpio25, Gpio26, Gpio27, Gpio32, Gpio33, Gpio34, Gpio35,
+    Gpio36, Gpio37, Gpio38, Gpio39
+);
+
+// NOTE: Gpio12 - Gpio17 are connected to the SPI flash and left out on purpose
+#[cfg(esp32c3)]
+interrupt_capable!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11, Gpio18,
+    Gpio19, Gpio20, Gpio21
+);
+
+// NOTE: Gpio26 - Gpio32 are connected to the SPI flash / PSRAM and left out on purpose
+#[cfg(esp32s2)]
+interrupt_capable!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11, Gpio12,
+    Gpio13, Gpio14, Gpio15, Gpio16, Gpio17, Gpio18, Gpio19, Gpio20, Gpio21, Gpio33, Gpio34, Gpio35,
+    Gpio36, Gpio37, Gpio38, Gpio39, Gpio40, Gpio41, Gpio42, Gpio43, Gpio44, Gpio45, Gpio46
+);
+
+// NOTE: Gpio26 - Gpio32 are connected to the SPI flash / PSRAM and left out on purpose
+#[cfg(esp32s3)]
+interrupt_capable!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11, Gpio12,
+    Gpio13, Gpio14, Gpio15, Gpio16, Gpio17, Gpio18, Gpio19, Gpio20, Gpio21, Gpio33, Gpio34, Gpio35,
+    Gpio36, Gpio37, Gpio38, Gpio39, Gpio40, Gpio41, Gpio42, Gpio43, Gpio44, Gpio45, Gpio46, Gpio47,
+    Gpio48
+);
+
+/// The pins reserved for the SPI flash on the current chip
+#[cfg(esp32)]
+const FLASH_PINS: RangeInclusive<i32> = 6..=11;
+#[cfg(esp32c3)]
+const FLASH_PINS: RangeInclusive<i32> = 12..=17;
+#[cfg(any(esp32s2, esp32s3))]
+const FLASH_PINS: RangeInclusive<i32> = 26..=32;
+#[cfg(not(any(esp32, esp32c3, esp32s2, esp32s3)))]
+#[allow(clippy::reversed_empty_ranges)]
+const FLASH_PINS: RangeInclusive<i32> = 0..=-1;
+
+/// Runtime counterpart of `InterruptCapable` for degraded pins, where the type doesn't tell
+pub(crate) fn check_interrupt_capable(pin: i32) -> Result<(), EspError> {
+    let in_range = (0..gpio_num_t_GPIO_NUM_MAX).contains(&pin);
+
+    match in_range && !FLASH_PINS.contains(&pin) {
+        true => Ok(()),
+        false => Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap()),
+    }
+}
+
+/// The internal pull resistors of an input pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PullMode {
+    Up,
+    Down,
+    UpDown,
+    Floating,
+}
+
+impl From<PullMode> for gpio_pull_mode_t {
+    fn from(pull: PullMode) -> Self {
+        match pull {
+            PullMode::Up => esp_idf_sys::gpio_pull_mode_t_GPIO_PULLUP_ONLY,
+            PullMode::Down => esp_idf_sys::gpio_pull_mode_t_GPIO_PULLDOWN_ONLY,
+            PullMode::UpDown => esp_idf_sys::gpio_pull_mode_t_GPIO_PULLUP_PULLDOWN,
+            PullMode::Floating => esp_idf_sys::gpio_pull_mode_t_GPIO_FLOATING,
+        }
+    }
+}
+
+/// How `LeveledEdge::from_pin_num` sets up the pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinConfig {
+    pub pull: PullMode,
+    /// See `LeveledEdgeBuilder::active`
+    pub active: Level,
+}
@@ -0,0 +1,71 @@
+use crate::debounce::{check_timer, Debounce};
+use crate::pins::check_interrupt_capable;
+use esp_idf_hal::gpio::{GpioPin, Input, Pin};
+use esp_idf_sys::{gpio_get_level, EspError};
+
+/// The polling counterpart of `LeveledEdge`, for main loops that don't want interrupts at all
+/// The pin is only read when `poll` (or `poll_iter`) is called, with the same debouncers.
+pub struct PolledEdge<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    gpio: GpioPin<Input>,
+    pin_state: bool,
+    debouncer: Debouncer,
+}
+
+impl<Debouncer> PolledEdge<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// Reads `poll_iter` does at most, so an oscillating pin can't keep it going forever
+    const POLL_ITER_MAX_READS: usize = 16;
+
+    pub fn new(gpio: GpioPin<Input>, debouncer: Debouncer) -> Result<Self, EspError> {
+        check_interrupt_capable(gpio.pin())?;
+        check_timer()?;
+        let pin_state = unsafe { gpio_get_level(gpio.pin()) } != 0;
+
+        Ok(Self {
+            gpio,
+            pin_state,
+            debouncer,
+        })
+    }
+
+    /// The current state of the pin as of the last poll
+    /// - `true` if the pin is high
+    /// - `false` if the pin is low
+    pub fn current_state(&self) -> bool {
+        self.pin_state
+    }
+
+    /// Read the pin once, returns the new state if it changed and the debouncer accepted the edge
+    pub fn poll(&mut self) -> Option<bool> {
+        let level = unsafe { gpio_get_level(self.gpio.pin()) } != 0;
+        if level == self.pin_state {
+            return None;
+        }
+
+        self.pin_state = level;
+        match self.debouncer.is_isr_valid() {
+            true => Some(level),
+            false => None,
+        }
+    }
+
+    /// Keep reading the pin until it stops changing, yielding every accepted edge on the way
+    /// Stops after `POLL_ITER_MAX_READS` reads even if the pin is still oscillating,
+    /// what's left is picked up by the next call.
+    pub fn poll_iter(&mut self) -> impl Iterator<Item = bool> + '_ {
+        (0..Self::POLL_ITER_MAX_READS)
+            .map_while(move |_| {
+                let state = self.pin_state;
+                let accepted = self.poll();
+
+                // the pin read the same as before, it settled
+                (self.pin_state != state).then_some(accepted)
+            })
+            .flatten()
+    }
+}
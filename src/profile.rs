@@ -0,0 +1,57 @@
+use crate::seqlock::SeqLock;
+use esp_idf_hal::cpu;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Clone, Copy, Default)]
+struct IsrTimes {
+    max: i64,
+    total: i64,
+    runs: u32,
+}
+
+/// How long the interrupt handler of a handle takes, measured from its entry to its exit
+pub(crate) struct IsrProfile {
+    times: IsrTimes,
+    published: SeqLock<IsrTimes>,
+    /// The core the isr ran on last, `u32::MAX` until it ran
+    core: AtomicU32,
+}
+
+impl IsrProfile {
+    pub(crate) fn new() -> Self {
+        Self {
+            times: IsrTimes::default(),
+            published: SeqLock::new(IsrTimes::default()),
+            core: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    /// Called from the isr on its way out, with the time it was entered
+    #[inline(always)]
+    pub(crate) fn record(&mut self, entry: i64, exit: i64) {
+        let took = exit - entry;
+
+        self.times.max = self.times.max.max(took);
+        self.times.total += took;
+        self.times.runs = self.times.runs.wrapping_add(1);
+        self.published.write(self.times);
+        self.core.store(cpu::core() as u32, Ordering::Relaxed);
+    }
+
+    pub(crate) fn max_micros(&self) -> i64 {
+        self.published.read().max
+    }
+
+    pub(crate) fn last_core(&self) -> u32 {
+        self.core.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn avg_micros(&self) -> i64 {
+        let times = self.published.read();
+
+        match times.runs {
+            0 => 0,
+            runs => times.total / runs as i64,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::debounce::{micros, Debounce};
+use crate::leveled_edge::LeveledEdge;
+use esp_idf_hal::gpio::{GpioPin, Input};
+use esp_idf_sys::EspError;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+type PulseCallback = Box<dyn FnMut(bool)>;
+
+/// Duration of the last complete high and low phases, 0 until measured
+struct Phases {
+    high: AtomicU32,
+    low: AtomicU32,
+}
+
+/// Measures the width of the high and low phases of a signal (PWM, pulse width encoded sensors...)
+///
+/// The timestamps are taken in the callback, so the resolution is bound by the interrupt latency,
+/// a few microseconds plus whatever the debouncer and other interrupts add. Fine for servo style pulses
+/// in the hundreds of microseconds and up, useless for fast PWM.
+/// Use `NoDebounce` unless the signal really bounces, a debouncer would eat short pulses.
+pub struct PulseMeter<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    phases: Arc<Phases>,
+    _interrupt: Box<LeveledEdge<Debouncer, PulseCallback>>,
+}
+
+impl<Debouncer> PulseMeter<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    pub fn new(gpio: GpioPin<Input>, debouncer: Debouncer) -> Result<Self, EspError> {
+        let phases = Arc::new(Phases {
+            high: AtomicU32::new(0),
+            low: AtomicU32::new(0),
+        });
+
+        let isr_phases = phases.clone();
+        let mut last_edge: Option<i64> = None;
+        let on_edge: PulseCallback = Box::new(move |level| {
+            let now = micros();
+
+            // the first edge only starts the first phase
+            if let Some(last) = last_edge {
+                let width = (now - last) as u32;
+                match level {
+                    true => isr_phases.low.store(width, Ordering::Release),
+                    false => isr_phases.high.store(width, Ordering::Release),
+                }
+            }
+
+            last_edge = Some(now);
+        });
+
+        Ok(PulseMeter {
+            phases,
+            _interrupt: LeveledEdge::new(gpio, debouncer, on_edge)?,
+        })
+    }
+
+    /// Microseconds the signal was high last time, 0 until a whole high phase was seen
+    pub fn last_high_us(&self) -> u32 {
+        self.phases.high.load(Ordering::Acquire)
+    }
+
+    /// Microseconds the signal was low last time, 0 until a whole low phase was seen
+    pub fn last_low_us(&self) -> u32 {
+        self.phases.low.load(Ordering::Acquire)
+    }
+
+    /// The share of the period spent high (0.0 - 1.0), from the last high and low phases
+    /// `None` until both were measured
+    pub fn duty_cycle(&self) -> Option<f32> {
+        let high = self.last_high_us();
+        let low = self.last_low_us();
+
+        match high == 0 || low == 0 {
+            true => None,
+            false => Some(high as f32 / (high as f32 + low as f32)),
+        }
+    }
+}
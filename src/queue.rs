@@ -0,0 +1,137 @@
+use esp_idf_hal::{delay::TickType, interrupt};
+use esp_idf_sys::{c_types::c_void, BaseType_t, QueueHandle_t};
+use std::marker::PhantomData;
+use std::mem::{size_of, MaybeUninit};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Where the items of an `IsrQueue` are sent from, which decides the send method it has
+pub trait SendPolicy: sealed::Sealed {}
+
+/// Sent from an interrupt handler with `send_from_isr`, never blocks (the default)
+pub struct FromIsr;
+/// Sent from a task with `send`, which may block waiting for room
+pub struct Blocking;
+
+impl sealed::Sealed for FromIsr {}
+impl sealed::Sealed for Blocking {}
+impl SendPolicy for FromIsr {}
+impl SendPolicy for Blocking {}
+
+/// A FreeRTOS queue for moving events out of an interrupt handler
+/// The item size is taken from `T`, and the yield after waking a higher priority task is handled on send.
+/// The policy `P` says from where the items are sent, so a queue fed by an isr has no blocking send
+/// to call by mistake, and the other way around.
+///
+/// ```compile_fail
+/// # use esp_idf_hal::delay::TickType;
+/// # use esp_leveled_edge::IsrQueue;
+/// let queue = IsrQueue::<u32>::new(8).unwrap();
+/// // fed by an isr, only `send_from_isr`
+/// queue.send(1, TickType(0));
+/// ```
+///
+/// ```compile_fail
+/// # use esp_leveled_edge::{Blocking, IsrQueue};
+/// let queue = IsrQueue::<u32, Blocking>::new(8).unwrap();
+/// // fed by a task, only `send`
+/// queue.send_from_isr(1);
+/// ```
+pub struct IsrQueue<T: Copy, P: SendPolicy = FromIsr> {
+    handle: QueueHandle_t,
+    _item: PhantomData<(T, P)>,
+}
+
+unsafe impl<T: Copy + Send, P: SendPolicy> Send for IsrQueue<T, P> {}
+unsafe impl<T: Copy + Send, P: SendPolicy> Sync for IsrQueue<T, P> {}
+
+impl<T: Copy, P: SendPolicy> IsrQueue<T, P> {
+    /// Create a queue that can hold up to `length` items
+    /// Returns `None` if FreeRTOS couldn't allocate it
+    pub fn new(length: u32) -> Option<Self> {
+        let handle = unsafe { esp_idf_sys::xQueueGenericCreate(length, size_of::<T>() as u32, 0) };
+
+        match handle.is_null() {
+            true => None,
+            false => Some(Self {
+                handle,
+                _item: PhantomData,
+            }),
+        }
+    }
+
+    /// Wait up to `timeout` for the next item, must be called from a task
+    pub fn recv(&self, timeout: TickType) -> Option<T> {
+        let mut item = MaybeUninit::<T>::uninit();
+
+        let received = unsafe {
+            esp_idf_sys::xQueueReceive(self.handle, item.as_mut_ptr() as *mut c_void, timeout.0)
+        } > 0;
+
+        match received {
+            true => Some(unsafe { item.assume_init() }),
+            false => None,
+        }
+    }
+
+    /// Drop all the items waiting in the queue
+    /// FreeRTOS resets the queue inside a critical section, so an item sent by an isr at the
+    /// same time either gets flushed or stays intact in the queue, it is never half written.
+    pub fn flush(&self) {
+        unsafe { esp_idf_sys::xQueueGenericReset(self.handle, 0) };
+    }
+
+    /// Blocking send from a task whatever the policy, for the crate's own shutdown paths
+    /// that run once the isr feeding the queue is already removed
+    pub(crate) fn send_from_task(&self, item: T, timeout: TickType) -> bool {
+        unsafe {
+            esp_idf_sys::xQueueGenericSend(
+                self.handle,
+                &item as *const _ as *const c_void,
+                timeout.0,
+                0,
+            ) > 0
+        }
+    }
+}
+
+impl<T: Copy> IsrQueue<T, FromIsr> {
+    /// Send an item from an interrupt handler, never blocks
+    /// Returns `false` if the queue is full and the item was dropped
+    #[inline(always)]
+    #[link_section = ".iram1.isr_queue_send"]
+    pub fn send_from_isr(&self, item: T) -> bool {
+        let mut higher_prio_task_woken: BaseType_t = Default::default();
+
+        let sent = unsafe {
+            esp_idf_sys::xQueueGenericSendFromISR(
+                self.handle,
+                &item as *const _ as *const c_void,
+                &mut higher_prio_task_woken as *mut _,
+                0,
+            )
+        } > 0;
+
+        if higher_prio_task_woken != 0 {
+            interrupt::task::do_yield();
+        }
+
+        sent
+    }
+}
+
+impl<T: Copy> IsrQueue<T, Blocking> {
+    /// Send an item from a task, waiting up to `timeout` for room in the queue
+    /// Returns `false` if the queue stayed full and the item was dropped
+    pub fn send(&self, item: T, timeout: TickType) -> bool {
+        self.send_from_task(item, timeout)
+    }
+}
+
+impl<T: Copy, P: SendPolicy> Drop for IsrQueue<T, P> {
+    fn drop(&mut self) {
+        unsafe { esp_idf_sys::vQueueDelete(self.handle) };
+    }
+}
@@ -0,0 +1,105 @@
+use crate::state::HandleState;
+use crate::telemetry::Counters;
+use esp_idf_sys::{gpio_num_t_GPIO_NUM_MAX, EspError};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+const SLOTS: usize = gpio_num_t_GPIO_NUM_MAX as usize;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: AtomicPtr<HandleState> = AtomicPtr::new(ptr::null_mut());
+
+/// Every live handle, indexed by its pin (a pin can only have one isr handler anyway)
+/// Changed and walked in a critical section, so a handle can't be dropped while `disable_all` /
+/// `enable_all` is using it
+static HANDLES: [AtomicPtr<HandleState>; SLOTS] = [EMPTY; SLOTS];
+
+pub(crate) fn register(state: &HandleState) {
+    critical_section::with(|_| {
+        HANDLES[state.pin as usize].store(state as *const _ as *mut _, Ordering::Release)
+    });
+}
+
+pub(crate) fn unregister(state: &HandleState) {
+    critical_section::with(|_| {
+        HANDLES[state.pin as usize].store(ptr::null_mut(), Ordering::Release)
+    });
+}
+
+fn for_each(mut f: impl FnMut(&HandleState) -> Result<(), EspError>) -> Result<(), EspError> {
+    critical_section::with(|_| {
+        let mut result = Ok(());
+
+        for slot in &HANDLES {
+            let state = slot.load(Ordering::Acquire);
+            if !state.is_null() {
+                // keep going on errors, one pin failing shouldn't leave the others alone
+                result = result.and(f(unsafe { &*state }));
+            }
+        }
+
+        result
+    })
+}
+
+/// What `crash_summary` tells about a handle, its pin and the counters of `LeveledEdgeTelemetry`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputSummary {
+    pub pin: i32,
+    /// See `LeveledEdgeBuilder::label`
+    pub label: Option<&'static str>,
+    /// The level the interrupt handler tracks, see `LeveledEdge::current_state`
+    pub state: bool,
+    pub enabled: bool,
+    pub edges: u32,
+    pub accepted: u32,
+    pub rejected: u32,
+    pub dropped: u32,
+    pub missed_edge_recoveries: u32,
+    pub callback_panics: u32,
+}
+
+/// Call `f` with the summary of every live handle, for a panic or abort handler that logs the inputs
+/// as they were at the time of the crash.
+///
+/// It takes no lock and allocates nothing, every field is a plain atomic load, so it's fine in a
+/// context where the heap or the scheduler may be broken. The flip side: a handle dropped by another
+/// task while this runs can be read after it's gone, which is why it is only meant for a crash,
+/// when nothing else runs anymore. `f` itself has to keep to the same rules (e.g. `esp_rom_printf`,
+/// not `println!`). The fields are read one by one, not as a single instant.
+pub fn crash_summary(mut f: impl FnMut(&InputSummary)) {
+    for slot in &HANDLES {
+        let state = slot.load(Ordering::Acquire);
+        if state.is_null() {
+            continue;
+        }
+
+        let state = unsafe { &*state };
+        let counters = &state.counters;
+        f(&InputSummary {
+            pin: state.pin,
+            label: state.label,
+            state: state.current_state(),
+            enabled: state.is_enabled(),
+            edges: Counters::load(&counters.edges),
+            accepted: Counters::load(&counters.accepted),
+            rejected: Counters::load(&counters.rejected),
+            dropped: Counters::load(&counters.dropped),
+            missed_edge_recoveries: Counters::load(&counters.missed_edge_recoveries),
+            callback_panics: Counters::load(&counters.callback_panics),
+        });
+    }
+}
+
+/// Disable the interrupt of every live handle at once, e.g. when entering a safe state
+/// or before a firmware update. Runs in a critical section, so it can be called from a fault handler.
+/// Returns the first error, after trying all the handles.
+pub fn disable_all() -> Result<(), EspError> {
+    for_each(HandleState::disable)
+}
+
+/// Enable every live handle again after `disable_all`, each one resynced to the current level of its pin
+/// (see `LeveledEdge::enable`). Handles that were disabled on their own are enabled too.
+pub fn enable_all() -> Result<(), EspError> {
+    for_each(HandleState::enable)
+}
@@ -0,0 +1,56 @@
+use std::cell::UnsafeCell;
+use std::hint::spin_loop;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+
+/// A single writer sequence lock
+/// Lets the interrupt handler publish values wider than the native atomics (esp32 only has 32 bit atomics)
+/// while tasks read them lock free and without tearing.
+pub(crate) struct SeqLock<T: Copy> {
+    seq: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Publish a new value
+    /// There must only be one writer at a time, normally the isr of the owning handle. A task may write
+    /// too, but only with that isr kept out (`LeveledEdge::isr_off`): a `read` in the isr spins until the write
+    /// is done, which never happens if it interrupted the write on the same core.
+    #[inline(always)]
+    pub(crate) fn write(&self, value: T) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Read a consistent copy of the value, retrying if a write was in progress
+    pub(crate) fn read(&self) -> T {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 1 {
+                spin_loop();
+                continue;
+            }
+
+            let value = unsafe { ptr::read_volatile(self.value.get()) };
+            fence(Ordering::Acquire);
+
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return value;
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use esp_idf_sys::{c_types::c_int, EspError, ESP_ERR_INVALID_STATE, ESP_OK};
+use std::sync::Mutex;
+
+/// Configuration of the global gpio isr service shared by all the handles
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IsrConfig {
+    pub(crate) flags: c_int,
+    pub(crate) assume_service_installed: bool,
+    pub(crate) uninstall_when_unused: bool,
+}
+
+impl IsrConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `ESP_INTR_FLAG_*` flags passed to `gpio_install_isr_service`, 0 by default
+    /// Only the first install counts, it's up to the app to keep these the same everywhere.
+    pub fn flags(mut self, flags: c_int) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Don't call `gpio_install_isr_service` at all, for when another crate or framework owns it.
+    /// If the service turns out not to be installed, creating the handle fails with
+    /// `ESP_ERR_INVALID_STATE` from `gpio_isr_handler_add`.
+    pub fn assume_service_installed(mut self, assume: bool) -> Self {
+        self.assume_service_installed = assume;
+        self
+    }
+
+    /// Call `gpio_uninstall_isr_service` when the last handle is dropped, if it was this crate that installed it.
+    /// Off by default since other code may be using the service too.
+    /// The handle dropped last decides, so set it the same on all of them.
+    pub fn uninstall_when_unused(mut self, uninstall: bool) -> Self {
+        self.uninstall_when_unused = uninstall;
+        self
+    }
+}
+
+struct ServiceState {
+    handles: usize,
+    installed_here: bool,
+}
+
+static SERVICE: Mutex<ServiceState> = Mutex::new(ServiceState {
+    handles: 0,
+    installed_here: false,
+});
+
+/// Install the gpio isr service unless told otherwise, it being already installed is fine
+/// Counts the handle as a user of the service on success, see `release_service`
+pub(crate) fn install_service(config: &IsrConfig) -> Result<(), EspError> {
+    let mut service = SERVICE.lock().unwrap();
+
+    if !config.assume_service_installed {
+        match unsafe { esp_idf_sys::gpio_install_isr_service(config.flags) } {
+            ESP_OK => service.installed_here = true,
+            ESP_ERR_INVALID_STATE => {}
+            err => return Err(EspError::from(err).unwrap()),
+        }
+    }
+
+    service.handles += 1;
+    Ok(())
+}
+
+/// A handle that got through `install_service` is gone, uninstall the service if it was the last one
+/// and `IsrConfig::uninstall_when_unused` says so
+pub(crate) fn release_service(config: &IsrConfig) {
+    let mut service = SERVICE.lock().unwrap();
+    service.handles -= 1;
+
+    if service.handles == 0 && service.installed_here && config.uninstall_when_unused {
+        unsafe { esp_idf_sys::gpio_uninstall_isr_service() };
+        service.installed_here = false;
+    }
+}
+
+/// The number of live handles using the gpio isr service, for diagnostics
+pub fn service_refcount() -> usize {
+    SERVICE.lock().unwrap().handles
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+type SettlingCallback = Box<dyn FnMut(Duration)>;
+
+/// Measures how long every accepted edge kept bouncing, see `LeveledEdgeBuilder::settling_meter`
+pub(crate) struct SettlingMeter {
+    callback: SettlingCallback,
+    /// The time of the last accepted edge, `None` until there is one
+    edge: Option<i64>,
+    /// The time of the last edge after it that was not accepted
+    last_bounce: i64,
+}
+
+impl SettlingMeter {
+    pub(crate) fn new(callback: impl FnMut(Duration) + 'static) -> Self {
+        Self {
+            callback: Box::new(callback),
+            edge: None,
+            last_bounce: 0,
+        }
+    }
+
+    /// Called from the isr on every edge the debouncer rejected (or that settled back)
+    #[inline(always)]
+    pub(crate) fn bounce(&mut self, now: i64) {
+        self.last_bounce = now;
+    }
+
+    /// Called from the isr on every accepted edge, reports the settling time of the previous one
+    #[inline(always)]
+    pub(crate) fn edge(&mut self, now: i64) {
+        if let Some(edge) = self.edge {
+            let settling = (self.last_bounce - edge).max(0);
+            (self.callback)(Duration::from_micros(settling as u64));
+        }
+
+        self.edge = Some(now);
+        self.last_bounce = now;
+    }
+}
@@ -0,0 +1,98 @@
+use crate::debounce::Debounce;
+use crate::leveled_edge::LeveledEdge;
+use esp_idf_hal::gpio::{GpioPin, Input, Output, Pin};
+use esp_idf_sys::{
+    esp_rom_delay_us, gpio_get_level, gpio_set_level, EspError, ESP_ERR_INVALID_ARG,
+};
+
+type LineCallback = Box<dyn FnMut(bool)>;
+
+/// Several buttons sharing a single interrupt line (wired-OR)
+///
+/// Every button connects the shared line to its own column output,
+/// the line is pulled up and all columns idle low, so pressing any button pulls the line low.
+/// On the falling edge the columns are scanned one by one to find out which buttons are pressed.
+///
+/// ```text
+///   line (pull up) ───┬────────┬────────┬──── ...
+///                    [b0]     [b1]     [b2]
+///   columns:         col0     col1     col2
+/// ```
+pub struct SharedLine<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    _interrupt: Box<LeveledEdge<Debouncer, LineCallback>>,
+}
+
+impl<Debouncer> SharedLine<Debouncer>
+where
+    Debouncer: Debounce,
+{
+    /// Time given to the line to settle after driving a column
+    const SETTLE_US: u32 = 2;
+
+    /// Create a new instance of `SharedLine`
+    /// line: The shared interrupt line, must be pulled up (internally or externally)
+    /// columns: One output per button, at most 32, fails with `ESP_ERR_INVALID_ARG` if there are more
+    /// callback: Called from the interrupt handler with a bitmask of the pressed buttons
+    /// (bit index = position in `columns`), `0` when all buttons were released
+    pub fn new(
+        line: GpioPin<Input>,
+        columns: Vec<GpioPin<Output>>,
+        debouncer: Debouncer,
+        mut callback: impl FnMut(u32) + 'static,
+    ) -> Result<Self, EspError> {
+        // the pressed buttons are reported as bits of a u32
+        if columns.len() > 32 {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG).unwrap());
+        }
+
+        let line_num = line.pin();
+        for col in &columns {
+            unsafe { gpio_set_level(col.pin(), 0) };
+        }
+
+        let scan: LineCallback = Box::new(move |state| {
+            let pressed = match state {
+                true => 0,
+                false => unsafe { Self::scan(line_num, &columns) },
+            };
+
+            callback(pressed);
+        });
+
+        let _interrupt = LeveledEdge::new(line, debouncer, scan)?;
+
+        Ok(SharedLine { _interrupt })
+    }
+
+    /// Release all columns, then drive them low one at a time and see which one pulls the line down.
+    /// All columns are driven low again before returning so the next press keeps triggering the line.
+    #[inline(always)]
+    #[link_section = ".iram1.shared_line_scan"]
+    unsafe fn scan(line: i32, columns: &[GpioPin<Output>]) -> u32 {
+        let mut pressed = 0;
+
+        for col in columns {
+            gpio_set_level(col.pin(), 1);
+        }
+
+        for (i, col) in columns.iter().enumerate() {
+            gpio_set_level(col.pin(), 0);
+            esp_rom_delay_us(Self::SETTLE_US);
+
+            if gpio_get_level(line) == 0 {
+                pressed |= 1 << i;
+            }
+
+            gpio_set_level(col.pin(), 1);
+        }
+
+        for col in columns {
+            gpio_set_level(col.pin(), 0);
+        }
+
+        pressed
+    }
+}
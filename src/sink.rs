@@ -0,0 +1,108 @@
+use crate::deferred::DeferredTask;
+use crate::queue::IsrQueue;
+use esp_idf_hal::interrupt;
+use esp_idf_sys::{BaseType_t, EspError, TaskHandle_t};
+use std::ptr;
+use std::sync::Arc;
+
+/// An accepted edge with what a consumer away from the handle needs to tell it apart, see `Sink::deferred`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EdgeEvent {
+    pub pin: i32,
+    /// See `LeveledEdgeBuilder::label`
+    pub label: Option<&'static str>,
+    /// See `LeveledEdgeBuilder::user_data`
+    pub user_data: u32,
+    /// Same as the argument of the callback
+    pub active: bool,
+    /// Time of the edge in microseconds, always `esp_timer` time (a `LeveledEdgeBuilder::clock` is not applied)
+    pub timestamp: i64,
+}
+
+/// An extra consumer of the edges of a handle, on top of its callback
+/// Added with `LeveledEdgeBuilder::sink`, every sink but `Deferred` runs in the interrupt handler like the callback.
+pub enum Sink {
+    /// Called with the same argument as the callback
+    Callback(Box<dyn FnMut(bool)>),
+    /// Gets the same argument as the callback sent to it, dropped if the queue is full
+    Queue(Arc<IsrQueue<bool>>),
+    /// Gets the same argument as the callback as its task notification value (1 or 0),
+    /// overwriting the previous one if it didn't wait for it yet. See `LeveledEdge::notify_task`
+    Notify(TaskHandle_t),
+    /// Handed to a task of its own, created with `Sink::deferred`
+    Deferred(DeferredSink),
+}
+
+/// The task behind `Sink::Deferred`
+pub struct DeferredSink(DeferredTask<EdgeEvent>);
+
+impl Sink {
+    /// The most sinks a handle can have, each one adds to the time spent in the isr
+    pub const MAX_SINKS: usize = 4;
+
+    /// A sink that forwards every edge as an `EdgeEvent` to `transport` in a task of its own, to turn
+    /// a button into a networked event source: `transport` sends it over ESP-NOW, MQTT or whatever the app uses.
+    ///
+    /// `transport` is only ever called from that task, never from the interrupt handler, so it may block,
+    /// allocate and talk to the network stack. The isr only queues the event (dropped if the queue is full,
+    /// e.g. while the network is stalled), see `LeveledEdge::new_deferred` for the task itself:
+    /// how it stops with the handle, its stack and priority, and the watchdog.
+    /// Fails with `ESP_ERR_INVALID_ARG` on a stack or priority out of range and with `ESP_ERR_NO_MEM`
+    /// if there is no room for the task.
+    pub fn deferred(
+        task_stack: u32,
+        task_prio: u32,
+        transport: impl FnMut(EdgeEvent) + Send + 'static,
+    ) -> Result<Self, EspError> {
+        let task = DeferredTask::spawn(task_stack, task_prio, Box::new(transport), None)?;
+        Ok(Sink::Deferred(DeferredSink(task)))
+    }
+
+    #[inline(always)]
+    pub(crate) fn deliver(&mut self, event: &EdgeEvent) {
+        match self {
+            Sink::Callback(callback) => callback(event.active),
+            Sink::Queue(queue) => {
+                queue.send_from_isr(event.active);
+            }
+            Sink::Notify(task) => notify_from_isr(*task, event.active as u32),
+            Sink::Deferred(DeferredSink(task)) => {
+                task.send_from_isr(*event);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+#[link_section = ".iram1.sink_notify"]
+pub(crate) fn notify_from_isr(task: TaskHandle_t, value: u32) {
+    let mut higher_prio_task_woken: BaseType_t = Default::default();
+
+    // esp-idf 4.4 added the index of the notification, the one `xTaskNotifyWait` waits on is 0
+    #[cfg(esp_idf_version = "4.3")]
+    unsafe {
+        esp_idf_sys::xTaskGenericNotifyFromISR(
+            task,
+            value,
+            esp_idf_sys::eNotifyAction_eSetValueWithOverwrite,
+            ptr::null_mut(),
+            &mut higher_prio_task_woken as *mut _,
+        )
+    };
+
+    #[cfg(not(esp_idf_version = "4.3"))]
+    unsafe {
+        esp_idf_sys::xTaskGenericNotifyFromISR(
+            task,
+            0,
+            value,
+            esp_idf_sys::eNotifyAction_eSetValueWithOverwrite,
+            ptr::null_mut(),
+            &mut higher_prio_task_woken as *mut _,
+        )
+    };
+
+    if higher_prio_task_woken != 0 {
+        interrupt::task::do_yield();
+    }
+}
@@ -0,0 +1,243 @@
+use crate::busy::Busy;
+use crate::debounce::micros;
+use crate::seqlock::SeqLock;
+use crate::telemetry::Counters;
+use esp_idf_hal::gpio::InterruptType;
+use esp_idf_sys::{
+    esp, gpio_get_level, gpio_intr_disable, gpio_intr_enable, gpio_set_intr_type, EspError,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// When the interrupt was turned off by `disable`, and how long it was off the last time
+#[derive(Clone, Copy, Default)]
+struct DisabledTimes {
+    since: Option<i64>,
+    last: Option<i64>,
+}
+
+/// The interrupt of a handle left off by the isr until its deferred task handled the edge,
+/// see `LeveledEdge::new_deferred_backpressure`. Shared by the handle and the task.
+pub(crate) struct HoldOff {
+    pin: i32,
+    held: AtomicBool,
+    /// The handle is gone, the task must not touch its interrupt anymore
+    closed: AtomicBool,
+}
+
+impl HoldOff {
+    pub(crate) fn new(pin: i32) -> Self {
+        Self {
+            pin,
+            held: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Called from the isr before the edge is queued for the task
+    #[inline(always)]
+    pub(crate) fn hold(&self) {
+        self.held.store(true, Ordering::Release);
+    }
+
+    /// Called from the isr if the edge didn't make it into the queue, the isr enables
+    /// the interrupt on its way out. No task has the edge, so none can race with this
+    #[inline(always)]
+    pub(crate) fn unhold(&self) {
+        self.held.store(false, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn is_held(&self) -> bool {
+        self.held.load(Ordering::Acquire)
+    }
+
+    /// Called from the task once it handled the edge, enables the interrupt the isr left off
+    /// (armed for the level after the edge, so one that came meanwhile fires right away).
+    /// In a critical section, so the task can't enable it while the handle disables it or goes away
+    pub(crate) fn release(&self) {
+        critical_section::with(|_| {
+            if self.held.swap(false, Ordering::AcqRel) && !self.closed.load(Ordering::Acquire) {
+                unsafe { gpio_intr_enable(self.pin) };
+            }
+        });
+    }
+
+    /// Forget the edge being handled, so releasing it doesn't undo a `disable`
+    fn cancel(&self) {
+        critical_section::with(|_| self.held.store(false, Ordering::Release));
+    }
+
+    /// The handle is being dropped, nothing may enable its interrupt from now on
+    pub(crate) fn close(&self) {
+        critical_section::with(|_| self.closed.store(true, Ordering::Release));
+    }
+}
+
+/// The part of a handle that doesn't depend on its debouncer or callback,
+/// so it can also be reached without knowing them (e.g. by the registry)
+pub(crate) struct HandleState {
+    pub(crate) pin: i32,
+    pub(crate) label: Option<&'static str>,
+    pub(crate) pin_state: AtomicBool,
+    pub(crate) enabled: AtomicBool,
+    /// A polling debouncer is sampling the pin, it enables the interrupt again once done
+    pub(crate) polling: AtomicBool,
+    /// The interrupt handler, kept out by `LeveledEdge::isr_off` while a task changes the handle
+    pub(crate) handler: Busy,
+    pub(crate) counters: Counters,
+    pub(crate) hold_off: Option<Arc<HoldOff>>,
+    disabled: SeqLock<DisabledTimes>,
+    /// When `enable` last turned the interrupt on, `None` while disabled
+    armed_at: SeqLock<Option<i64>>,
+}
+
+impl HandleState {
+    /// Starts disabled, it is enabled once the handle is armed
+    pub(crate) fn new(
+        pin: i32,
+        label: Option<&'static str>,
+        pin_state: bool,
+        hold_off: Option<Arc<HoldOff>>,
+    ) -> Self {
+        Self {
+            pin,
+            label,
+            pin_state: AtomicBool::new(pin_state),
+            enabled: AtomicBool::new(false),
+            polling: AtomicBool::new(false),
+            handler: Busy::new(),
+            counters: Counters::new(),
+            hold_off,
+            disabled: SeqLock::new(DisabledTimes::default()),
+            armed_at: SeqLock::new(None),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn current_state(&self) -> bool {
+        self.pin_state.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_polling(&self) -> bool {
+        self.polling.load(Ordering::Acquire)
+    }
+
+    /// Whether the interrupt may be enabled (by the isr on its way out, or a task done changing
+    /// the handle): the handle is enabled, the interrupt isn't held off for a deferred task,
+    /// and no polling debouncer is sampling the pin
+    #[inline(always)]
+    pub(crate) fn may_enable(&self) -> bool {
+        let held = match &self.hold_off {
+            Some(hold_off) => hold_off.is_held(),
+            None => false,
+        };
+
+        self.is_enabled() && !held && !self.is_polling()
+    }
+
+    /// The interrupt type to arm for the tracked pin state, the opposite level
+    #[inline(always)]
+    pub(crate) fn next_interrupt(&self) -> InterruptType {
+        match self.current_state() {
+            true => InterruptType::LowLevel,
+            false => InterruptType::HighLevel,
+        }
+    }
+
+    /// How long the interrupt was off between the last `disable` and the `enable` after it
+    pub(crate) fn last_disabled_duration(&self) -> Option<Duration> {
+        self.disabled
+            .read()
+            .last
+            .map(|micros| Duration::from_micros(micros as u64))
+    }
+
+    pub(crate) fn armed_at(&self) -> Option<i64> {
+        self.armed_at.read()
+    }
+
+    pub(crate) fn disable(&self) -> Result<(), EspError> {
+        if let Some(hold_off) = &self.hold_off {
+            hold_off.cancel();
+        }
+
+        if self.enabled.swap(false, Ordering::AcqRel) {
+            self.armed_at.write(None);
+            let times = self.disabled.read();
+            self.disabled.write(DisabledTimes {
+                since: Some(micros()),
+                ..times
+            });
+        }
+
+        esp!(unsafe { gpio_intr_disable(self.pin) })
+    }
+
+    /// Read the pin again and re-arm the trigger level to match it, then enable the interrupt
+    pub(crate) fn enable(&self) -> Result<(), EspError> {
+        self.resync()?;
+        self.enable_armed()
+    }
+
+    /// Read the pin again and re-arm the trigger level to match it, returns the level.
+    /// The interrupt has to be off, it is left off.
+    pub(crate) fn resync(&self) -> Result<bool, EspError> {
+        let level = unsafe { gpio_get_level(self.pin) } != 0;
+        self.rearm(level)?;
+
+        Ok(level)
+    }
+
+    /// Track the pin at `level` and arm the trigger for the opposite one
+    fn rearm(&self, level: bool) -> Result<(), EspError> {
+        self.pin_state.store(level, Ordering::Release);
+
+        esp!(unsafe { gpio_set_intr_type(self.pin, self.next_interrupt().into()) })
+    }
+
+    /// Enable the interrupt, for the level `resync` armed it for.
+    /// Left off if it is held off or a polling debouncer is sampling, they enable it once done.
+    pub(crate) fn enable_armed(&self) -> Result<(), EspError> {
+        self.enabled.store(true, Ordering::Release);
+        // taken right before, an edge can't be seen earlier than this
+        self.armed_at.write(Some(micros()));
+        if self.may_enable() {
+            esp!(unsafe { gpio_intr_enable(self.pin) })?;
+        }
+
+        let times = self.disabled.read();
+        if let Some(since) = times.since {
+            self.disabled.write(DisabledTimes {
+                since: None,
+                last: Some(micros() - since),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rearmed_for_the_new_idle_level() {
+        // a pull up idles high, armed for the press pulling it low
+        let state = HandleState::new(4, None, true, None);
+        assert!(matches!(state.next_interrupt(), InterruptType::LowLevel));
+
+        // `set_pull` to a pull down, the line settles low
+        state.rearm(false).unwrap();
+        assert!(!state.current_state());
+        assert!(matches!(state.next_interrupt(), InterruptType::HighLevel));
+    }
+}
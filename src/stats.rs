@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Number of buckets in the interval histogram
+pub const HISTOGRAM_BUCKETS: usize = 7;
+
+/// Upper bound (exclusive) in microseconds of every bucket but the last, which takes the rest
+/// <100us, <1ms, <10ms, <100ms, <1s, <10s, >=10s
+pub const HISTOGRAM_LIMITS_MICROS: [i64; HISTOGRAM_BUCKETS - 1] =
+    [100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+
+/// Accepted edges `LeveledEdge::recommend_debounce_time` needs before it recommends anything,
+/// 10 presses with their releases
+pub const RECOMMEND_MIN_EDGES: u32 = 20;
+
+/// Histogram of the time between consecutive edges seen by the interrupt handler,
+/// including the ones rejected by the debouncer, so it shows how the switch really bounces.
+/// Also keeps the longest bounce after an accepted edge and the shortest gap between two accepted ones,
+/// for `LeveledEdge::recommend_debounce_time`.
+pub(crate) struct IntervalHistogram {
+    buckets: [AtomicU32; HISTOGRAM_BUCKETS],
+    last_edge: Option<i64>,
+    last_accepted: Option<i64>,
+    accepted: AtomicU32,
+    /// Microseconds from an accepted edge to the last edge rejected after it, the longest so far
+    longest_tail: AtomicU32,
+    /// Microseconds between two accepted edges, the shortest so far
+    shortest_gap: AtomicU32,
+}
+
+impl IntervalHistogram {
+    /// The recommendation is the longest bounce plus this much of it, rounded up to the millisecond
+    const MARGIN_PERCENT: u64 = 25;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: Default::default(),
+            last_edge: None,
+            last_accepted: None,
+            accepted: AtomicU32::new(0),
+            longest_tail: AtomicU32::new(0),
+            shortest_gap: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    /// Called from the isr after the debouncer decided on the edge recorded last
+    #[inline(always)]
+    pub(crate) fn decided(&mut self, now: i64, accepted: bool) {
+        let since = self
+            .last_accepted
+            .map(|last| (now - last).min(u32::MAX as i64) as u32);
+
+        match (accepted, since) {
+            (true, since) => {
+                if let Some(gap) = since {
+                    self.shortest_gap.fetch_min(gap, Ordering::Relaxed);
+                }
+                self.accepted.fetch_add(1, Ordering::Relaxed);
+                self.last_accepted = Some(now);
+            }
+            (false, Some(tail)) => {
+                self.longest_tail.fetch_max(tail, Ordering::Relaxed);
+            }
+            (false, None) => {}
+        }
+    }
+
+    pub(crate) fn recommend_debounce_time(&self) -> Option<Duration> {
+        if self.accepted.load(Ordering::Relaxed) < RECOMMEND_MIN_EDGES {
+            return None;
+        }
+
+        let tail = self.longest_tail.load(Ordering::Relaxed) as u64;
+        let micros = tail + tail * Self::MARGIN_PERCENT / 100;
+        let recommended = Duration::from_millis(micros / 1000 + 1);
+
+        // no window keeps the bounces out and lets the fastest presses in
+        match recommended.as_micros() < self.shortest_gap.load(Ordering::Relaxed) as u128 {
+            true => Some(recommended),
+            false => None,
+        }
+    }
+
+    /// Called from the isr on every edge
+    #[inline(always)]
+    pub(crate) fn record(&mut self, now: i64) {
+        if let Some(last_edge) = self.last_edge {
+            let interval = now - last_edge;
+            let bucket = HISTOGRAM_LIMITS_MICROS
+                .iter()
+                .position(|limit| interval < *limit)
+                .unwrap_or(HISTOGRAM_BUCKETS - 1);
+
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.last_edge = Some(now);
+    }
+
+    pub(crate) fn snapshot(&self) -> [u32; HISTOGRAM_BUCKETS] {
+        let mut histogram = [0; HISTOGRAM_BUCKETS];
+        for (count, bucket) in histogram.iter_mut().zip(&self.buckets) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+
+        histogram
+    }
+}
@@ -0,0 +1,81 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A snapshot of the counters of a handle, see `LeveledEdge::telemetry`
+/// Every field is read atomically on its own, the struct as a whole is not one consistent instant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LeveledEdgeTelemetry {
+    /// See `LeveledEdgeBuilder::label`
+    pub label: Option<&'static str>,
+    /// Times the interrupt handler ran
+    pub edges: u32,
+    /// Edges the debouncer accepted
+    pub accepted: u32,
+    /// Edges the debouncer rejected
+    pub rejected: u32,
+    /// Accepted edges that were not delivered, because the handle was muted or the gate didn't allow them
+    pub dropped: u32,
+    /// Accepted edges that went to the same level as the previous accepted one,
+    /// meaning the edge in between was rejected and the level interrupt caught up on it
+    pub missed_edge_recoveries: u32,
+    /// Times the callback panicked, each one disabled the handle.
+    /// Only caught with the `catch_panics` feature, without it a panic brings the device down as usual
+    pub callback_panics: u32,
+    /// The current state of the pin, see `LeveledEdge::current_state`
+    pub state: bool,
+    /// See `LeveledEdge::time_in_state_micros`
+    pub time_in_state_micros: i64,
+}
+
+/// The counters behind `LeveledEdgeTelemetry`, only written by the interrupt handler
+pub(crate) struct Counters {
+    pub(crate) edges: AtomicU32,
+    pub(crate) accepted: AtomicU32,
+    pub(crate) rejected: AtomicU32,
+    pub(crate) dropped: AtomicU32,
+    pub(crate) missed_edge_recoveries: AtomicU32,
+    pub(crate) callback_panics: AtomicU32,
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            edges: AtomicU32::new(0),
+            accepted: AtomicU32::new(0),
+            rejected: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+            missed_edge_recoveries: AtomicU32::new(0),
+            callback_panics: AtomicU32::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn bump(counter: &AtomicU32) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn load(counter: &AtomicU32) -> u32 {
+        counter.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(
+        &self,
+        label: Option<&'static str>,
+        state: bool,
+        time_in_state_micros: i64,
+    ) -> LeveledEdgeTelemetry {
+        LeveledEdgeTelemetry {
+            label,
+            edges: Self::load(&self.edges),
+            accepted: Self::load(&self.accepted),
+            rejected: Self::load(&self.rejected),
+            dropped: Self::load(&self.dropped),
+            missed_edge_recoveries: Self::load(&self.missed_edge_recoveries),
+            callback_panics: Self::load(&self.callback_panics),
+            state,
+            time_in_state_micros,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::busy::Busy;
+use esp_idf_sys::{c_types::c_void, esp, esp_timer_create_args_t, esp_timer_handle_t, EspError};
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+type TimerCallback = Box<dyn FnMut() + Send>;
+
+/// Every `esp_timer` the crate created and didn't delete yet (the handle's address)
+static TIMERS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// The number of `esp_timer`s created by the crate that are still alive.
+/// Every helper that uses one (`FrequencyCounter`, `PollDebounce`...) deletes it when dropped,
+/// so it is 0 once they are all gone, a cheap check for leaks on shutdown.
+/// Debug builds also assert on every drop that the timer was in the count.
+pub fn timer_count() -> usize {
+    TIMERS.lock().unwrap().len()
+}
+
+/// Stop every `esp_timer` the crate created, e.g. right before a deep sleep or a restart.
+/// The timers are only stopped, not deleted, their owners still delete them when dropped.
+/// A `PollDebounce` handle stopped in the middle of sampling stays off until `LeveledEdge::enable`.
+pub fn stop_all_timers() {
+    for handle in TIMERS.lock().unwrap().iter() {
+        unsafe { esp_idf_sys::esp_timer_stop(*handle as esp_timer_handle_t) };
+    }
+}
+
+/// The callback and whether it is running, what the esp_timer task is handed
+struct Dispatch {
+    callback: UnsafeCell<TimerCallback>,
+    busy: Busy,
+}
+
+/// An `esp_timer` calling a closure from the esp_timer task, stopped and deleted on drop
+pub(crate) struct EspTimer {
+    handle: esp_timer_handle_t,
+    dispatch: Box<Dispatch>,
+}
+
+unsafe impl Send for EspTimer {}
+
+impl EspTimer {
+    const NAME: &'static [u8] = b"leveled_edge\0";
+
+    pub(crate) fn new(callback: impl FnMut() + Send + 'static) -> Result<Self, EspError> {
+        let dispatch = Box::new(Dispatch {
+            callback: UnsafeCell::new(Box::new(callback)),
+            busy: Busy::new(),
+        });
+        let mut handle: esp_timer_handle_t = ptr::null_mut();
+
+        let args = esp_timer_create_args_t {
+            callback: Some(Self::dispatch),
+            arg: dispatch.as_ref() as *const Dispatch as *mut c_void,
+            dispatch_method: esp_idf_sys::esp_timer_dispatch_t_ESP_TIMER_TASK,
+            name: Self::NAME.as_ptr() as *const _,
+            skip_unhandled_events: true,
+        };
+
+        esp!(unsafe { esp_idf_sys::esp_timer_create(&args, &mut handle) })?;
+        TIMERS.lock().unwrap().push(handle as usize);
+
+        Ok(Self { handle, dispatch })
+    }
+
+    pub(crate) fn start_periodic(&self, period: Duration) -> Result<(), EspError> {
+        esp!(unsafe {
+            esp_idf_sys::esp_timer_start_periodic(self.handle, period.as_micros() as u64)
+        })
+    }
+
+    /// Same as `start_periodic` for the isr, no `Duration` math and in IRAM.
+    /// Fails if the timer is running already, which is fine for the callers
+    #[inline(always)]
+    #[link_section = ".iram1.esp_timer_start"]
+    pub(crate) fn start_periodic_from_isr(&self, period_us: u64) {
+        unsafe { esp_idf_sys::esp_timer_start_periodic(self.handle, period_us) };
+    }
+
+    /// Start a one shot from the isr, no `Duration` math and in IRAM.
+    /// Fails if the timer is running already, which leaves the running one to expire as it was
+    #[inline(always)]
+    #[link_section = ".iram1.esp_timer_start_once"]
+    pub(crate) fn start_once_from_isr(&self, timeout_us: u64) {
+        unsafe { esp_idf_sys::esp_timer_start_once(self.handle, timeout_us) };
+    }
+
+    pub(crate) fn stop(&self) {
+        unsafe { esp_idf_sys::esp_timer_stop(self.handle) };
+    }
+
+    /// Stop the timer, and wait for the callback to return if it is running right now.
+    /// `esp_timer_stop` alone doesn't wait, and the esp_timer task may be about to run the callback
+    /// even after it returned, so the callback is also kept from running again until `reopen`.
+    /// Once this returns, whatever the callback uses can be changed or freed.
+    /// Must not be called from the timer's own callback, it would wait for itself.
+    pub(crate) fn stop_and_wait(&self) {
+        self.stop();
+
+        while !self.dispatch.busy.try_close() {
+            unsafe { esp_idf_sys::vTaskDelay(1) };
+        }
+    }
+
+    /// Let the callback run again after `stop_and_wait`, the timer is not started again
+    pub(crate) fn reopen(&self) {
+        self.dispatch.busy.reopen();
+    }
+
+    unsafe extern "C" fn dispatch(arg: *mut c_void) {
+        let dispatch = &*(arg as *const Dispatch);
+        if !dispatch.busy.enter() {
+            return;
+        }
+
+        // only ever called from the esp_timer task, one callback at a time
+        (*dispatch.callback.get())();
+        dispatch.busy.leave();
+    }
+}
+
+impl Drop for EspTimer {
+    fn drop(&mut self) {
+        let mut timers = TIMERS.lock().unwrap();
+        let position = timers
+            .iter()
+            .position(|handle| *handle == self.handle as usize);
+        debug_assert!(position.is_some(), "esp_timer missing from the registry");
+        if let Some(position) = position {
+            timers.swap_remove(position);
+        }
+
+        // the callback is freed with `self`, it must not be running anymore
+        self.stop_and_wait();
+        unsafe { esp_idf_sys::esp_timer_delete(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debounce::Poller;
+
+    #[test]
+    fn dropped_timers_are_deleted() {
+        let before = timer_count();
+
+        // the way the handles come and go, e.g. a `PollDebounce` handle recreated on every reconfiguration
+        for _ in 0..100 {
+            let timer = EspTimer::new(|| {}).unwrap();
+            let _poller = Poller::new(EspTimer::new(|| {}).unwrap(), Duration::from_millis(1), 3);
+            assert_eq!(timer_count(), before + 2);
+            drop(timer);
+        }
+
+        assert_eq!(timer_count(), before);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::seqlock::SeqLock;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The number of edges a handle remembers, unless set with `LeveledEdgeBuilder::trace_depth`
+pub const DEFAULT_TRACE_DEPTH: usize = 32;
+
+/// An edge as the interrupt handler saw it, see `LeveledEdge::trace`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TraceEntry {
+    /// Time of the edge in microseconds, `esp_timer` time unless the handle has a clock (`LeveledEdgeBuilder::clock`)
+    pub timestamp: i64,
+    /// The level the pin settled at
+    pub level: bool,
+    /// The label of the handle (`LeveledEdgeBuilder::label`), filled in by `LeveledEdge::trace`
+    pub label: Option<&'static str>,
+    /// Whether the debouncer accepted the edge, muted or gated edges still count as accepted
+    pub accepted: bool,
+    /// An accepted edge to the same level as the previous accepted one, see `LeveledEdgeTelemetry`
+    pub missed_recovery: bool,
+}
+
+/// The last edges of a handle, written by the interrupt handler and read back by `LeveledEdge::trace`
+/// Every entry is a `SeqLock` of its own, so writing one costs the isr a couple of stores.
+pub(crate) struct TraceRing {
+    entries: Vec<SeqLock<TraceEntry>>,
+    next: AtomicUsize,
+    wrapped: AtomicBool,
+}
+
+impl TraceRing {
+    pub(crate) fn new(depth: usize) -> Self {
+        Self {
+            entries: (0..depth)
+                .map(|_| SeqLock::new(TraceEntry::default()))
+                .collect(),
+            next: AtomicUsize::new(0),
+            wrapped: AtomicBool::new(false),
+        }
+    }
+
+    /// Called from the isr, overwrites the oldest entry once the ring is full
+    #[inline(always)]
+    pub(crate) fn record(&self, entry: TraceEntry) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let next = self.next.load(Ordering::Relaxed);
+        self.entries[next].write(entry);
+
+        if next + 1 == self.entries.len() {
+            self.wrapped.store(true, Ordering::Release);
+            self.next.store(0, Ordering::Release);
+        } else {
+            self.next.store(next + 1, Ordering::Release);
+        }
+    }
+
+    /// The entries oldest first
+    /// Each entry is read consistently, but edges coming in while copying may replace the oldest ones.
+    pub(crate) fn snapshot(&self) -> Vec<TraceEntry> {
+        let next = self.next.load(Ordering::Acquire);
+
+        match self.wrapped.load(Ordering::Acquire) {
+            true => self.entries[next..]
+                .iter()
+                .chain(&self.entries[..next])
+                .map(SeqLock::read)
+                .collect(),
+            false => self.entries[..next].iter().map(SeqLock::read).collect(),
+        }
+    }
+}
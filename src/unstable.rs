@@ -0,0 +1,60 @@
+use crate::debounce::window_micros;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+type UnstableCallback = Box<dyn FnMut()>;
+
+/// Flags a pin whose edges keep being rejected, see `LeveledEdgeBuilder::detect_unstable`
+pub(crate) struct UnstableDetector {
+    threshold: u32,
+    window: i64,
+    /// Rejected edges in a row, since `run_start`
+    run: u32,
+    run_start: i64,
+    unstable: AtomicBool,
+    callback: Option<UnstableCallback>,
+}
+
+impl UnstableDetector {
+    pub(crate) fn new(
+        threshold: u32,
+        window: Duration,
+        callback: Option<UnstableCallback>,
+    ) -> Self {
+        Self {
+            threshold,
+            window: window_micros(window),
+            run: 0,
+            run_start: 0,
+            unstable: AtomicBool::new(false),
+            callback,
+        }
+    }
+
+    /// Called from the isr on every edge, accepted or not (settled back included)
+    #[inline(always)]
+    pub(crate) fn edge(&mut self, accepted: bool, now: i64) {
+        if accepted {
+            self.run = 0;
+            self.unstable.store(false, Ordering::Release);
+            return;
+        }
+
+        if self.run == 0 || now - self.run_start > self.window {
+            self.run = 0;
+            self.run_start = now;
+        }
+        self.run = self.run.saturating_add(1);
+
+        // reported once, when it becomes unstable
+        if self.run > self.threshold && !self.unstable.swap(true, Ordering::AcqRel) {
+            if let Some(callback) = &mut self.callback {
+                callback();
+            }
+        }
+    }
+
+    pub(crate) fn is_unstable(&self) -> bool {
+        self.unstable.load(Ordering::Acquire)
+    }
+}